@@ -0,0 +1,47 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use unimotion_rs::prelude::*;
+use unimotion_rs::unimotion::sim::Simulator;
+
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn usage() -> ! {
+    eprintln!("Usage: unistation-sim tcp <address:port>");
+    eprintln!("       unistation-sim serial <path> [baud rate]");
+    eprintln!();
+    eprintln!("A pty pair can be created with `socat pty,raw,echo=0,link=/tmp/unistation pty,raw,echo=0,link=/tmp/unimotion`.");
+    std::process::exit(2)
+}
+
+fn main() -> UnimotionResult<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["tcp", address] => {
+            let listener = TcpListener::bind(address)?;
+            println!("Simulating a UniStation on {}", listener.local_addr()?);
+            for stream in listener.incoming() {
+                let stream = stream?;
+                println!("Connection from {}", stream.peer_addr()?);
+                let transport = TcpTransport::new(stream, READ_TIMEOUT)?;
+                if let Err(e) = Simulator::new().run(Box::new(transport)) {
+                    eprintln!("Connection closed: {}", e);
+                }
+            }
+            Ok(())
+        },
+        ["serial", path, rest @ ..] => {
+            let baud_rate = match rest {
+                [] => 230_400,
+                [baud_rate] => baud_rate.parse().unwrap_or_else(|_| usage()),
+                _ => usage(),
+            };
+            println!("Simulating a UniStation on {}", path);
+            let transport = SerialTransport::open(path, baud_rate, READ_TIMEOUT)?;
+            Simulator::new().run(Box::new(transport))?;
+            Ok(())
+        },
+        _ => usage(),
+    }
+}
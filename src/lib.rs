@@ -1,6 +1,5 @@
 extern crate serialport;
 extern crate macaddr;
-#[macro_use]
 extern crate lazy_static;
 
 pub mod unimotion;
@@ -17,29 +16,83 @@ pub mod prelude {
     pub use crate::result::*;
     pub use crossbeam_channel;
     pub(crate) use serialport;
-    #[cfg(feature = "use_serde")]
+    #[cfg(feature = "serde")]
     pub(crate) use serde::{Deserialize, Serialize};
 }
 
 pub mod result {
+    use std::error::Error;
+    use std::fmt::{Display, Formatter};
     use std::io::Error as IOError;
-    use crossbeam_channel::{SendError, SendTimeoutError};
     use serialport::Error as SerialportError;
 
-    use crate::unimotion::device::AcknowledgeType;
+    use crate::unimotion::{UnimotionSerialNumber, StationId};
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_source_chain() {
+            let e = UnimotionError::from(ParseError::UnknownAckToken(String::from("NOPE")));
+            assert_eq!(e.to_string(), "report error: invalid line: unknown acknowledge `_ok NOPE`");
+
+            let report = e.source().unwrap();
+            assert_eq!(report.to_string(), "invalid line: unknown acknowledge `_ok NOPE`");
+            let parse = report.source().unwrap();
+            assert_eq!(parse.to_string(), "unknown acknowledge `_ok NOPE`");
+            assert!(parse.source().is_none());
+        }
+
+        #[test]
+        fn test_timeout() {
+            let e = UnimotionError::from_recv_timeout(crossbeam_channel::RecvTimeoutError::Timeout, "_ch");
+            assert_eq!(e.to_string(), "timed out waiting for `_ch`");
+        }
+    }
 
     #[derive(Debug)]
     pub enum UnimotionError {
         IOError(IOError),
         SerialportError(SerialportError),
-        SubCommandError(u8, Vec<u8>),
         UnimotionDeviceError(UnimotionDeviceError),
         UnimotionReportError(UnimotionReportError),
         Disconnected,
-        // TODO: Dispatch into their corresponding errors
-        PlaceholderError(PlaceholderError),
-        // CrossbeamChannelError(CrossbeamChannelError<T>)
-        CrossbeamChannelError,
+        Timeout { waiting_for: &'static str },
+        ChannelClosed,
+        PairingError(PairingError),
+        /// Error of one station of a `StationSet`.
+        Station(StationId, Box<UnimotionError>),
+    }
+
+    impl Display for UnimotionError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                UnimotionError::IOError(e) => write!(f, "I/O error: {}", e),
+                UnimotionError::SerialportError(e) => write!(f, "serial port error: {}", e),
+                UnimotionError::UnimotionDeviceError(e) => write!(f, "device error: {}", e),
+                UnimotionError::UnimotionReportError(e) => write!(f, "report error: {}", e),
+                UnimotionError::Disconnected => write!(f, "the UniStation is disconnected"),
+                UnimotionError::Timeout { waiting_for } => write!(f, "timed out waiting for `{}`", waiting_for),
+                UnimotionError::ChannelClosed => write!(f, "the ingress thread stopped"),
+                UnimotionError::PairingError(e) => write!(f, "pairing failed: {}", e),
+                UnimotionError::Station(id, e) => write!(f, "station {}: {}", id.0, e),
+            }
+        }
+    }
+
+    impl Error for UnimotionError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                UnimotionError::IOError(e) => Some(e),
+                UnimotionError::SerialportError(e) => Some(e),
+                UnimotionError::UnimotionDeviceError(e) => Some(e),
+                UnimotionError::UnimotionReportError(e) => Some(e),
+                UnimotionError::PairingError(e) => Some(e),
+                UnimotionError::Station(_, e) => Some(e.as_ref()),
+                _ => None,
+            }
+        }
     }
 
     impl From<SerialportError> for UnimotionError {
@@ -54,24 +107,65 @@ pub mod result {
         }
     }
 
-    impl From<PlaceholderError> for UnimotionError {
-        fn from(e: PlaceholderError) -> Self {
-            UnimotionError::PlaceholderError(e)
+    impl From<crossbeam_channel::RecvError> for UnimotionError {
+        fn from(_: crossbeam_channel::RecvError) -> Self {
+            UnimotionError::ChannelClosed
         }
     }
 
+    impl UnimotionError {
+        /// Map a failed `recv_timeout` while waiting for `waiting_for`.
+        pub fn from_recv_timeout(e: crossbeam_channel::RecvTimeoutError, waiting_for: &'static str) -> Self {
+            match e {
+                crossbeam_channel::RecvTimeoutError::Timeout => UnimotionError::Timeout { waiting_for },
+                crossbeam_channel::RecvTimeoutError::Disconnected => UnimotionError::ChannelClosed,
+            }
+        }
+    }
 
     #[derive(Debug)]
     pub enum UnimotionDeviceError {
         InvalidVendorID(u16),
         InvalidProductID(u16),
-        FailedStickParameterLoading,
-        FailedStickCalibrationLoading,
-        FailedIMUOffsetsLoading,
-        FailedIMUCalibrationLoading,
-        FailedColorLoading,
+        /// No UniSensor is paired in this slot of the UniStation.
+        SensorNotPaired(u8),
+        /// The sensor id is not below `MAX_UNISENSOR_COUNT`.
+        SensorIdOutOfRange(u8),
+        /// The sensor did not answer `__sensinfo` in time.
+        SensorNotResponding(u8),
+        /// The sensor did not store the requested magnetometer thresholds.
+        MagneticCalibrationRejected { id: u8, min: u8, max: u8 },
+        /// The UniStation restarted while streaming.
+        StationReset,
+        /// The sensor kept reporting its old settings after every retry.
+        CommandNotConfirmed(u8),
+        /// No sensor mode streams at this rate with this power setting.
+        UnsupportedSensorMode { rate: u32, low_power: bool },
+        /// No connected UniStation has this USB serial number.
+        StationNotFound(UnimotionSerialNumber),
     }
 
+    impl Display for UnimotionDeviceError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                UnimotionDeviceError::InvalidVendorID(vid) => write!(f, "invalid USB vendor ID {:#06x}", vid),
+                UnimotionDeviceError::InvalidProductID(pid) => write!(f, "invalid USB product ID {:#06x}", pid),
+                UnimotionDeviceError::SensorNotPaired(id) => write!(f, "no UniSensor is paired as sensor {}", id),
+                UnimotionDeviceError::SensorIdOutOfRange(id) => write!(f, "sensor id {} is out of range", id),
+                UnimotionDeviceError::SensorNotResponding(id) => write!(f, "sensor {} is not responding", id),
+                UnimotionDeviceError::MagneticCalibrationRejected { id, min, max } =>
+                    write!(f, "sensor {} rejected the magnetic thresholds {}..{}", id, min, max),
+                UnimotionDeviceError::StationReset => write!(f, "the UniStation restarted during the session"),
+                UnimotionDeviceError::CommandNotConfirmed(id) => write!(f, "sensor {} did not apply the command", id),
+                UnimotionDeviceError::UnsupportedSensorMode { rate, low_power: false } => write!(f, "sensors cannot stream at {} fps", rate),
+                UnimotionDeviceError::UnsupportedSensorMode { rate, low_power: true } => write!(f, "sensors cannot stream at {} fps in low power", rate),
+                UnimotionDeviceError::StationNotFound(serial_number) => write!(f, "no UniStation with serial number {} is connected", serial_number.0),
+            }
+        }
+    }
+
+    impl Error for UnimotionDeviceError {}
+
     impl From<UnimotionDeviceError> for UnimotionError {
         fn from(e: UnimotionDeviceError) -> Self {
             UnimotionError::UnimotionDeviceError(e)
@@ -80,9 +174,23 @@ pub mod result {
 
     #[derive(Debug)]
     pub enum UnimotionReportError {
-        InvalidSimpleHidReport(InvalidSimpleHIDReport),
-        InvalidStandardInputReport(InvalidStandardInputReport),
-        EmptyReport,
+        ParseError(ParseError),
+    }
+
+    impl Display for UnimotionReportError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                UnimotionReportError::ParseError(e) => write!(f, "invalid line: {}", e),
+            }
+        }
+    }
+
+    impl Error for UnimotionReportError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                UnimotionReportError::ParseError(e) => Some(e),
+            }
+        }
     }
 
     impl From<UnimotionReportError> for UnimotionError {
@@ -91,59 +199,98 @@ pub mod result {
         }
     }
 
+    /// Step of a `PairingSession` that failed.
     #[derive(Debug)]
-    pub enum InvalidSimpleHIDReport {
-        InvalidReport(Vec<u8>),
-        InvalidStickDirection(u8),
+    pub enum PairingError {
+        /// The station did not restart into config mode or did not start its radio.
+        EnterConfig(Box<UnimotionError>),
+        /// No new sensor showed up before the timeout.
+        NoNewSensor,
+        /// `_savepairing` could not be sent or checked.
+        Save(Box<UnimotionError>),
+        /// The accepted sensor was no longer listed once the pairing was saved.
+        SensorLost(u8),
+        /// The station did not go back to run mode.
+        QuitConfig(Box<UnimotionError>),
     }
 
-    impl From<InvalidSimpleHIDReport> for UnimotionReportError {
-        fn from(e: InvalidSimpleHIDReport) -> Self {
-            UnimotionReportError::InvalidSimpleHidReport(e)
+    impl Display for PairingError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                PairingError::EnterConfig(e) => write!(f, "could not enter config mode: {}", e),
+                PairingError::NoNewSensor => write!(f, "no new sensor showed up"),
+                PairingError::Save(e) => write!(f, "could not save the pairing: {}", e),
+                PairingError::SensorLost(id) => write!(f, "sensor {} was not saved", id),
+                PairingError::QuitConfig(e) => write!(f, "could not quit config mode: {}", e),
+            }
         }
     }
 
-    impl From<InvalidSimpleHIDReport> for UnimotionError {
-        fn from(e: InvalidSimpleHIDReport) -> Self {
-            let report_error = UnimotionReportError::from(e);
-            UnimotionError::from(report_error)
+    impl Error for PairingError {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            match self {
+                PairingError::EnterConfig(e) | PairingError::Save(e) | PairingError::QuitConfig(e) => Some(e.as_ref()),
+                PairingError::NoNewSensor | PairingError::SensorLost(_) => None,
+            }
         }
     }
 
-    #[derive(Debug)]
-    pub enum InvalidStandardInputReport {
-        InvalidReport(Vec<u8>),
-        InvalidExtraReport(Vec<u8>),
-        Battery(u8),
-        ConnectionInfo(u8),
-        InvalidInputReportId(u8),
+    impl From<PairingError> for UnimotionError {
+        fn from(e: PairingError) -> Self {
+            UnimotionError::PairingError(e)
+        }
     }
 
-    impl From<InvalidStandardInputReport> for UnimotionReportError {
-        fn from(e: InvalidStandardInputReport) -> Self {
-            UnimotionReportError::InvalidStandardInputReport(e)
-        }
+    /// Reason a line received from the UniStation could not be parsed.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum ParseError {
+        EmptyLine,
+        UnknownPrefix(String),
+        InvalidBase64(String),
+        WrongFieldCount { expected: usize, got: usize },
+        InvalidInteger(String),
+        UnknownAckToken(String),
+        UnsupportedDatagramLength(usize),
+        UnsupportedSensorInfoLength(usize),
+        BinaryLine(usize),
+        LineTooLong(usize),
+        InvalidTarget(String),
+        UnknownArguments(String),
     }
 
-    impl From<InvalidStandardInputReport> for UnimotionError {
-        fn from(e: InvalidStandardInputReport) -> Self {
-            let report_error = UnimotionReportError::from(e);
-            UnimotionError::from(report_error)
+    impl Display for ParseError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            match self {
+                ParseError::EmptyLine => write!(f, "empty line"),
+                ParseError::UnknownPrefix(prefix) => write!(f, "unknown prefix `{}`", prefix),
+                ParseError::InvalidBase64(e) => write!(f, "invalid base64: {}", e),
+                ParseError::WrongFieldCount { expected, got } => write!(f, "expected {} fields, got {}", expected, got),
+                ParseError::InvalidInteger(word) => write!(f, "invalid integer `{}`", word),
+                ParseError::UnknownAckToken(token) => write!(f, "unknown acknowledge `_ok {}`", token),
+                ParseError::UnsupportedDatagramLength(n) => write!(f, "unsupported datagram length of {} bytes", n),
+                ParseError::UnsupportedSensorInfoLength(n) => write!(f, "unsupported sensor info length of {} bytes", n),
+                ParseError::BinaryLine(n) => write!(f, "binary line of {} bytes", n),
+                ParseError::LineTooLong(n) => write!(f, "line of {} bytes is too long", n),
+                ParseError::InvalidTarget(word) => write!(f, "invalid sensor target `{}`", word),
+                ParseError::UnknownArguments(args) => write!(f, "unknown arguments `{}`", args),
+            }
         }
     }
 
-// TODO: Dispatch into their corresponding errors
-// BEGIN
-    #[derive(Debug)]
-    pub enum PlaceholderError {
-        UnexpectedAckError(AcknowledgeType, AcknowledgeType),
+    impl Error for ParseError {}
+
+    impl From<ParseError> for UnimotionReportError {
+        fn from(e: ParseError) -> Self {
+            UnimotionReportError::ParseError(e)
+        }
     }
 
-    pub enum CrossbeamChannelError<T>{
-        SendError(SendError<T>),
-        SendTimeoutError(SendTimeoutError<T>),
+    impl From<ParseError> for UnimotionError {
+        fn from(e: ParseError) -> Self {
+            let report_error = UnimotionReportError::from(e);
+            UnimotionError::from(report_error)
+        }
     }
-// END
 
     pub type UnimotionResult<T> = Result<T, UnimotionError>;
 }
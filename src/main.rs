@@ -7,19 +7,14 @@ use std::time::Duration;
 use unimotion_rs::prelude::*;
 
 fn main() -> UnimotionResult<()> {
-    let manager = UnimotionManager::get_instance();
+    let mut manager = UnimotionManagerBuilder::new().build()?;
     
     // std::thread::sleep(Duration::from_secs(5));
 
-    let mut manager = match manager.lock() {
-        Ok(m) => m,
-        Err(m) => m.into_inner(),
-    };
-    // manager.send_command(Command::RequestSensorInfo(255));
-    manager.send_command(Command::ListSensor);
+    // manager.send_command(Command::RequestSensorInfo(SensorTarget::All));
+    manager.refresh_sensor_list();
 
     loop {
         std::thread::sleep(Duration::from_secs(1));   
     }
-    Ok(())
 }
@@ -0,0 +1,13 @@
+//! Captures of real UniStation sessions, shared by the tests.
+
+pub(crate) const INITIALIZATION: &str = include_str!("../../doc/initialization");
+pub(crate) const SENSOR_READINGS: &str = include_str!("../../doc/sensor_readings");
+
+/// Raw bytes of every `Read <n> bytes: [...]` entry of a capture in `doc`.
+pub(crate) fn captured_lines(capture: &str) -> Vec<Vec<u8>> {
+    capture.lines()
+        .filter_map(|line| line.strip_prefix("Read "))
+        .filter_map(|line| line.split_once('[').and_then(|(_, bytes)| bytes.strip_suffix(']')))
+        .map(|bytes| bytes.split(", ").map(|b| b.parse::<u8>().unwrap()).collect())
+        .collect()
+}
@@ -0,0 +1,231 @@
+//! Incremental decoding of the byte stream sent by the UniStation.
+//!
+//! Reads on the serial port return arbitrary chunks: half a line, several lines, or the binary
+//! burst printed right after `_ok ESP_RESTART`. `ResponseDecoder` buffers them and only hands
+//! complete lines to `Response::try_from`.
+use super::*;
+use device::{Response, is_boot_noise_byte, BOOT_NOISE_MIN_LENGTH};
+
+use std::collections::VecDeque;
+
+/// Longest line the decoder buffers, the longest valid line (`_si`) is well under 64 bytes.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 256;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use device::AcknowledgeType;
+    use crate::unimotion::captures::{captured_lines, INITIALIZATION, SENSOR_READINGS};
+
+    /// xorshift64, good enough to pick split points.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    fn decode_all(decoder: &mut ResponseDecoder, bytes: &[u8]) -> Vec<String> {
+        decoder.push(bytes).map(|res| format!("{:?}", res)).collect()
+    }
+
+    fn decode_split(bytes: &[u8], rng: &mut Rng) -> Vec<String> {
+        let mut decoder = ResponseDecoder::new();
+        let mut res = Vec::new();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let (chunk, tail) = rest.split_at((1 + rng.below(48)).min(rest.len()));
+            res.extend(decode_all(&mut decoder, chunk));
+            rest = tail;
+        }
+        res
+    }
+
+    #[test]
+    fn test_captured_splits() {
+        for capture in [INITIALIZATION, SENSOR_READINGS] {
+            let bytes = captured_lines(capture).concat();
+            let mut decoder = ResponseDecoder::new();
+            let expected: Vec<_> = decoder.push(&bytes).collect();
+
+            assert!(expected.iter().all(|res| res.is_ok()));
+            assert!(matches!(expected[1], Ok(Response::BootNoise(519))));
+
+            let expected: Vec<_> = expected.iter().map(|res| format!("{:?}", res)).collect();
+            let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+            for _ in 0..64 {
+                assert_eq!(decode_split(&bytes, &mut rng), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_garbage_resync() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let mut bytes = Vec::new();
+        for line in captured_lines(SENSOR_READINGS) {
+            // Binary garbage never contains a line terminator, the CR LF after it does the resync
+            let garbage: Vec<u8> = (0..rng.below(600)).map(|_| 0x80 | rng.next() as u8).collect();
+            bytes.extend(garbage);
+            bytes.extend(b"\r\n");
+            bytes.extend(line);
+        }
+
+        for _ in 0..16 {
+            let mut decoder = ResponseDecoder::new();
+            let mut count = 0;
+            let mut rest = bytes.as_slice();
+            while !rest.is_empty() {
+                let (chunk, tail) = rest.split_at((1 + rng.below(96)).min(rest.len()));
+                for res in decoder.push(chunk) {
+                    match res {
+                        Ok(Response::Data(data)) => { assert_eq!(data.id, 7); count += 1 },
+                        Ok(_) => (),
+                        Err(ParseError::BinaryLine(_)) => (),
+                        Err(e) => panic!("unexpected {:?}", e),
+                    }
+                }
+                rest = tail;
+            }
+            assert_eq!(count, 593);
+        }
+    }
+
+    #[test]
+    fn test_binary_line() {
+        let mut decoder = ResponseDecoder::with_max_line_length(16);
+        let mut bytes = vec![0xff; 40];
+        bytes.extend(b"\r\n");
+        bytes.extend(crate::unimotion::sim::BOOT_NOISE);
+        bytes.extend(b"\r\n");
+        let res: Vec<_> = decoder.push(&bytes).collect();
+        assert!(matches!(res[..], [Err(ParseError::BinaryLine(40)), Ok(Response::BootNoise(519))]));
+    }
+
+    #[test]
+    fn test_partial_line() {
+        let mut decoder = ResponseDecoder::new();
+        assert!(decoder.push(b"_ok WIF").next().is_none());
+        assert!(matches!(
+            decoder.push(b"I_ON\r").collect::<Vec<_>>()[..],
+            [Ok(Response::Acknowledge(AcknowledgeType::StartWifi))]
+        ));
+        // The LF closing the line is not an empty line
+        assert!(decoder.push(b"\n").next().is_none());
+    }
+
+    #[test]
+    fn test_line_length_cap() {
+        let mut decoder = ResponseDecoder::with_max_line_length(16);
+        let mut res = Vec::new();
+        for _ in 0..10 {
+            res.extend(decoder.push(&[b'A'; 100]));
+        }
+        assert!(res.is_empty());
+
+        let res: Vec<_> = decoder.push(b"\r\n_ok\r\n").collect();
+        assert!(matches!(
+            res[..],
+            [Err(ParseError::LineTooLong(1000)), Ok(Response::Acknowledge(AcknowledgeType::Alive))]
+        ));
+    }
+}
+
+/// Splits the byte stream of a UniStation into lines and parses them.
+///
+/// Both CR and LF end a line, so the decoder resyncs on whichever it sees first and
+/// the `\r\n` pair never produces an empty line. A line holding anything but printable
+/// ASCII is either the boot noise, decoded as `Response::BootNoise`, or reported as
+/// `ParseError::BinaryLine`. A line longer than the cap is reported as `ParseError::LineTooLong`.
+/// None of them are buffered past the cap.
+#[derive(Debug)]
+pub struct ResponseDecoder {
+    line: Vec<u8>,
+    max_line_length: usize,
+    // Bytes of the current line dropped past the cap
+    overflow: usize,
+    binary: bool,
+    // Every byte of the current line so far could be boot noise
+    noise: bool,
+    ready: VecDeque<Result<Response, ParseError>>,
+}
+
+impl Default for ResponseDecoder {
+    fn default() -> Self {
+        Self::with_max_line_length(DEFAULT_MAX_LINE_LENGTH)
+    }
+}
+
+impl ResponseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_line_length(max_line_length: usize) -> Self {
+        ResponseDecoder {
+            line: Vec::with_capacity(max_line_length),
+            max_line_length,
+            overflow: 0,
+            binary: false,
+            noise: true,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Feed the next chunk read from the UniStation and take the lines it completed.
+    pub fn push(&mut self, chunk: &[u8]) -> impl Iterator<Item = Result<Response, ParseError>> + '_ {
+        for &byte in chunk {
+            match byte {
+                b'\r' | b'\n' => self.end_line(),
+                _ => {
+                    if !(byte.is_ascii_graphic() || byte == b' ') {
+                        self.binary = true;
+                    }
+                    self.noise &= is_boot_noise_byte(byte);
+                    if self.line.len() < self.max_line_length {
+                        self.line.push(byte);
+                    } else {
+                        self.overflow += 1;
+                    }
+                },
+            }
+        }
+        self.ready.drain(..)
+    }
+
+    /// Number of bytes of the line that is not terminated yet.
+    pub fn pending(&self) -> usize {
+        self.line.len() + self.overflow
+    }
+
+    fn end_line(&mut self) {
+        let length = self.pending();
+        if length == 0 {
+            return;
+        }
+
+        let res = if self.binary && self.noise && length >= BOOT_NOISE_MIN_LENGTH {
+            Ok(Response::BootNoise(length))
+        } else if self.binary {
+            Err(ParseError::BinaryLine(length))
+        } else if self.overflow > 0 {
+            Err(ParseError::LineTooLong(length))
+        } else {
+            Response::try_from(self.line.as_slice())
+        };
+        self.ready.push_back(res);
+
+        self.line.clear();
+        self.overflow = 0;
+        self.binary = false;
+        self.noise = true;
+    }
+}
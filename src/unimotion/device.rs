@@ -1,53 +1,77 @@
+use crate::result::ParseError;
+
+use super::mode::SensorMode;
 use macaddr::MacAddr6;
-use base64::{Engine as _, alphabet, engine::{self, general_purpose}};
+use base64::{Engine as _, alphabet, engine::{general_purpose, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig}};
 
 use std::str::FromStr;
 
+/// Standard alphabet, accepting datagrams with or without padding.
+const DATAGRAM_ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::unimotion::captures::{captured_lines, INITIALIZATION, SENSOR_READINGS};
 
     #[test]
     fn test_si() -> Result<(), String> {
         let expected_id = 7;
-        let expected_info = SensorInfo {
-            sensor_version: 102,
-            mystery_value: 78,
-            mac_address: MacAddr6::from_str("08:3A:F2:6D:1D:98").unwrap(),
-            channel: 1,
-            tx_power: 10,
-            datamode: 3,
-            six_axis: false,
-            imu_flip: true,
-            min_mag_th: 0,
-            max_mag_th: 124,
+        let expected_bytes = [102, 78, 8, 58, 242, 109, 29, 152, 1, 10, 90, 3, 2, 0, 4, 0, 0, 0, 1, 40, 8, 0, 124];
+
+        let info = match Response::try_from("_si 7 Zk4IOvJtHZgBCloDAgAEAAAAASgIAHw=".as_bytes()) {
+            Ok(Response::SensorInfo(id, info)) if id == expected_id => info,
+            r => return Err(format!("Expected _si {} and received {:?}", expected_id, r)),
         };
-        
-        match Response::from("_si 7 Zk4IOvJtHZgBCloDAgAEAAAAASgIAHw=".as_bytes().to_vec()) {
-            Response::SensorInfo(id, info) => {
-                assert_eq!(expected_id, id);
-                assert_eq!(expected_info, info);
+        assert_eq!(info.raw(), expected_bytes);
+        assert_eq!(info.sensor_version(), 102);
+        assert_eq!(info.mystery_value(), [78]);
+        assert_eq!(info.mac_address(), MacAddr6::from_str("08:3A:F2:6D:1D:98").unwrap());
+        assert_eq!(info.channel(), 1);
+        assert_eq!(info.tx_power(), 2.5);
+        assert_eq!(info.datamode(), 3);
+        assert_eq!(info.mode(), Some(SensorMode::FPS_60));
+        assert!(!info.six_axis());
+        assert!(info.imu_flip());
+        assert_eq!(info.magnetic_thresholds(), Some((0, 124)));
+        assert_eq!(info.max_magnetic_threshold(), Some(124));
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_si() {
+        let bytes: [u8; SHORT_SENSOR_INFO_LENGTH] = [102, 78, 8, 58, 242, 109, 29, 152, 1, 10, 90, 3, 2, 0, 4, 0, 0, 0, 1];
+        let line = format!("_si 7 {}", general_purpose::STANDARD.encode(bytes));
+        match Response::try_from(line.as_bytes()) {
+            Ok(Response::SensorInfo(7, info)) => {
+                assert_eq!(info, SensorInfo::from(bytes));
+                assert_eq!(info.raw(), bytes);
+                assert_eq!(info.channel(), 1);
+                assert_eq!(info.magnetic_thresholds(), None);
+                assert_eq!(info.min_magnetic_threshold(), None);
             },
-            r => return Err(format!(
-                "Expected {:?} and received {:?}", Response::SensorInfo(expected_id, expected_info), r)
-            )
+            r => panic!("unexpected {:?}", r),
         }
-        Ok(())
     }
 
     #[test]
     fn test_dev() -> Result<(), String> {
-        let addr1 = MacAddr6::from_str("E8:68:E7:53:55:DE");
+        let addr1 = MacAddr6::from_str("E8:68:E7:53:55:DE").unwrap();
         let addr2 = MacAddr6::nil();
         assert!(
             matches!(
-                Response::from("_dev 2 E8 68 E7 53 55 DE".as_bytes().to_vec()), 
-                Response::Device(2, addr1))
+                Response::try_from("_dev 2 E8 68 E7 53 55 DE".as_bytes()), 
+                Ok(Response::Device(2, addr)) if addr == addr1)
         );
         assert!(
             matches!(
-                Response::from("_dev 23 0 0 0 0 0 0".as_bytes().to_vec()), 
-                Response::Device(23, addr2))
+                Response::try_from("_dev 23 0 0 0 0 0 0".as_bytes()), 
+                Ok(Response::Device(23, addr)) if addr == addr2)
         );
         Ok(())
     }
@@ -55,10 +79,10 @@ mod tests {
     #[test]
     fn test_ch() -> Result<(), String> {
         assert!(
-            matches!(Response::from("_ch 1".as_bytes().to_vec()), Response::Channel(1))
+            matches!(Response::try_from("_ch 1".as_bytes()), Ok(Response::Channel(1)))
         );
         assert!(
-            matches!(Response::from("_ch 2".as_bytes().to_vec()), Response::Channel(2))
+            matches!(Response::try_from("_ch 2".as_bytes()), Ok(Response::Channel(2)))
         );
         Ok(())
     }
@@ -66,7 +90,7 @@ mod tests {
     #[test]
     fn test_auto_off() -> Result<(), String> {
         assert!(
-            matches!(Response::from("_auto_off 1 300000".as_bytes().to_vec()), Response::AutoOff(1, 300000))
+            matches!(Response::try_from("_auto_off 1 300000".as_bytes()), Ok(Response::AutoOff(1, 300000)))
         );
         Ok(())
     }
@@ -75,16 +99,16 @@ mod tests {
     fn test_ok() -> Result<(), String> {
         assert!(
             // vec![95, 111, 107, 13, 10] is "_ok\r\n"
-            matches!(Response::from(vec![95, 111, 107, 13, 10]), Response::Acknowledge(AcknowledgeType::Alive))
+            matches!(Response::try_from(&[95, 111, 107, 13, 10][..]), Ok(Response::Acknowledge(AcknowledgeType::Alive)))
         );
         assert!(
-            matches!(Response::from("_ok ESP_RESTART".as_bytes().to_vec()), Response::Acknowledge(AcknowledgeType::RestartAP))
+            matches!(Response::try_from("_ok ESP_RESTART".as_bytes()), Ok(Response::Acknowledge(AcknowledgeType::RestartAP)))
         );
         assert!(
-            matches!(Response::from("_ok WIFI_ON".as_bytes().to_vec()), Response::Acknowledge(AcknowledgeType::StartWifi))
+            matches!(Response::try_from("_ok WIFI_ON".as_bytes()), Ok(Response::Acknowledge(AcknowledgeType::StartWifi)))
         );
         assert!(
-            matches!(Response::from("_ok QUIT_CONFIG".as_bytes().to_vec()), Response::Acknowledge(AcknowledgeType::QuitConfig))
+            matches!(Response::try_from("_ok QUIT_CONFIG".as_bytes()), Ok(Response::Acknowledge(AcknowledgeType::QuitConfig)))
         );
         Ok(())
     }
@@ -92,10 +116,10 @@ mod tests {
     #[test]
     fn test_datamode() -> Result<(), String> {
         assert!(
-            matches!(Response::from("_datamode 1".as_bytes().to_vec()), Response::Datamode(1))
+            matches!(Response::try_from("_datamode 1".as_bytes()), Ok(Response::Datamode(1)))
         );
         assert!(
-            matches!(Response::from("_datamode 3".as_bytes().to_vec()), Response::Datamode(3))
+            matches!(Response::try_from("_datamode 3".as_bytes()), Ok(Response::Datamode(3)))
         );
         Ok(())
     }
@@ -121,16 +145,169 @@ mod tests {
                 None,
                 None,
             ],
-            ahrs_enable: 128,
-            magnetic_power: 255,
+            ahrs_enable: Some(128),
+            magnetic_power: Some(255),
+        };
+
+        match Response::try_from("B6cdte627NJ+Gxy1rbZs058bgP8".as_bytes()) {
+            Ok(Response::Data(data)) => assert_eq!(expected_data, data),
+            r => return Err(format!("Expected {:?} and received {:?}", Response::Data(expected_data), r))
+        }
+        Ok(())
+    }
+
+    // Golden datagrams built from id 3, battery 0xa0, the quaternions below, ahrs 0x80 and magnetic power 0x7f:
+    // 11 01 22 02 33 03 44 04
+    // 55 05 66 06 77 07 88 08
+    // 99 f9 aa fa bb fb cc fc
+    // dd 0d ee 0e ff 0f 00 10
+    fn check_layout(line: &str, quaternion_count: usize, extended: bool) -> Result<(), String> {
+        let signed = |x: u16| x as i16;
+        let golden = [
+            Quaternion { x: 0x0444, y: 0x0222, z: -0x0333, w: 0x0111 },
+            Quaternion { x: 0x0888, y: 0x0666, z: -0x0777, w: 0x0555 },
+            Quaternion { x: signed(0xfccc), y: signed(0xfaaa), z: -signed(0xfbbb), w: signed(0xf999) },
+            Quaternion { x: 0x1000, y: 0x0eee, z: -0x0fff, w: 0x0ddd },
+        ];
+        let mut quaternions = [None; 4];
+        for (i, q) in golden.iter().take(quaternion_count).enumerate() {
+            quaternions[i] = Some(*q);
+        }
+
+        let expected_data = Datagram {
+            id: 3,
+            battery_voltage: 0xa0,
+            quaternions,
+            ahrs_enable: if extended { Some(0x80) } else { None },
+            magnetic_power: if extended { Some(0x7f) } else { None },
         };
 
-        match Response::from("B6cdte627NJ+Gxy1rbZs058bgP8".as_bytes().to_vec()) {
-            Response::Data(data) => assert_eq!(expected_data, data),
+        match Response::try_from(line.as_bytes()) {
+            Ok(Response::Data(data)) => assert_eq!(expected_data, data),
             r => return Err(format!("Expected {:?} and received {:?}", Response::Data(expected_data), r))
         }
         Ok(())
     }
+
+    #[test]
+    fn test_data_ibq() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBA", 1, false)
+    }
+
+    #[test]
+    fn test_data_ibqam() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBIB/", 1, true)
+    }
+
+    #[test]
+    fn test_data_ibqq() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBFUFZgZ3B4gI", 2, false)
+    }
+
+    #[test]
+    fn test_data_ibqqam() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBFUFZgZ3B4gIgH8", 2, true)
+    }
+
+    #[test]
+    fn test_data_ibqqqq() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBFUFZgZ3B4gImfmq+rv7zPzdDe4O/w8AEA", 4, false)
+    }
+
+    #[test]
+    fn test_data_ibqqqqam() -> Result<(), String> {
+        check_layout("A6ARASICMwNEBFUFZgZ3B4gImfmq+rv7zPzdDe4O/w8AEIB/", 4, true)
+    }
+
+    #[test]
+    fn test_data_z_min() {
+        // Z = 0x8000 has no positive counterpart, -Z wraps back to itself
+        let q = Quaternion { x: 0x0444, y: 0x0222, z: i16::MIN, w: 0x0111 };
+        for (line, quaternion_count, extended) in [
+            ("A6ARASICAIBEBA", 1, false),
+            ("A6ARASICAIBEBIB/", 1, true),
+            ("A6ARASICAIBEBBEBIgIAgEQE", 2, false),
+            ("A6ARASICAIBEBBEBIgIAgEQEgH8", 2, true),
+            ("A6ARASICAIBEBBEBIgIAgEQEEQEiAgCARAQRASICAIBEBA", 4, false),
+            ("A6ARASICAIBEBBEBIgIAgEQEEQEiAgCARAQRASICAIBEBIB/", 4, true),
+        ] {
+            let data = Datagram::decode(line).unwrap();
+            assert_eq!(data.quaternions.iter().flatten().count(), quaternion_count, "{}", line);
+            assert!(data.quaternions.iter().flatten().all(|quaternion| *quaternion == q), "{}", line);
+            assert_eq!(data.ahrs_enable.is_some(), extended, "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let parse = |line: &str| Response::try_from(line.as_bytes()).unwrap_err();
+        assert_eq!(parse("\r\n"), ParseError::EmptyLine);
+        assert_eq!(parse("_foo 1"), ParseError::UnknownPrefix(String::from("_foo")));
+        assert_eq!(parse("_dev 2 E8 68 E7 53 55"), ParseError::WrongFieldCount { expected: 7, got: 6 });
+        assert_eq!(parse("_dev 2 E8 68 E7 53 55 XX"), ParseError::InvalidInteger(String::from("XX")));
+        assert_eq!(parse("_ch -1"), ParseError::InvalidInteger(String::from("-1")));
+        assert_eq!(parse("_auto_off 1 forever"), ParseError::InvalidInteger(String::from("forever")));
+        assert_eq!(parse("_ok SOMETHING"), ParseError::UnknownAckToken(String::from("SOMETHING")));
+        assert_eq!(parse("_si 7 Zk4IOvJtHZgBCloD"), ParseError::UnsupportedSensorInfoLength(12));
+        assert!(matches!(parse("_si 7 Zk4IOvJt*"), ParseError::InvalidBase64(_)));
+        assert!(matches!(parse("B6cd*e627NJ"), ParseError::InvalidBase64(_)));
+        assert_eq!(parse("B6cdte627NJ+Gxy1rbZs058bgP8A"), ParseError::UnsupportedDatagramLength(21));
+    }
+
+    #[test]
+    fn test_data_padding() {
+        let unpadded = Datagram::decode("B6cdte627NJ+Gxy1rbZs058bgP8").unwrap();
+        let padded = Datagram::decode("B6cdte627NJ+Gxy1rbZs058bgP8=").unwrap();
+        assert_eq!(unpadded, padded);
+
+        let unpadded = Datagram::decode("A6ARASICMwNEBA").unwrap();
+        let padded = Datagram::decode("A6ARASICMwNEBA==").unwrap();
+        assert_eq!(unpadded, padded);
+    }
+
+    #[test]
+    fn test_data_unsupported_length() {
+        // IBQQAM followed by a stray byte
+        assert_eq!(
+            Datagram::decode("B6cdte627NJ+Gxy1rbZs058bgP8A"),
+            Err(ParseError::UnsupportedDatagramLength(21))
+        );
+        assert_eq!(Datagram::try_from(&[7u8; 11][..]), Err(ParseError::UnsupportedDatagramLength(11)));
+        assert!(matches!(Datagram::decode("B6cd*e627NJ"), Err(ParseError::InvalidBase64(_))));
+    }
+
+    #[test]
+    fn test_boot_noise() {
+        let noise = captured_lines(INITIALIZATION).into_iter().find(|line| !line.is_ascii()).unwrap();
+        assert!(matches!(Response::try_from(noise.as_slice()), Ok(Response::BootNoise(519))));
+        assert!(is_boot_noise(&crate::unimotion::sim::BOOT_NOISE));
+
+        // Odd bytes never come out of the half-rate UART
+        let mut garbled = noise.clone();
+        garbled[100] = 0xff;
+        assert!(!is_boot_noise(&garbled[..519]));
+        assert!(!is_boot_noise(&noise[..8]));
+    }
+
+    #[test]
+    fn test_captured_datagrams() {
+        let mut count = 0;
+        for line in captured_lines(SENSOR_READINGS) {
+            // Skip text responses and the boot noise
+            if line.starts_with(b"_") || !line.is_ascii() {
+                continue;
+            }
+            match Response::try_from(line.as_slice()) {
+                Ok(Response::Data(data)) => {
+                    assert_eq!(data.id, 7);
+                    assert!(data.quaternions[1].is_some());
+                },
+                r => panic!("{:?} parsed as {:?}", String::from_utf8_lossy(&line), r),
+            }
+            count += 1;
+        }
+        assert_eq!(count, 593);
+    }
 }
 
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
@@ -150,7 +327,7 @@ impl UniSensorDevice {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Response {
     SensorInfo(u8, SensorInfo),// _si
     Device(u8, MacAddr6),// _dev
@@ -159,10 +336,30 @@ pub enum Response {
     Acknowledge(AcknowledgeType),// _ok
     Datamode(u8),// _datamode
     Data(Datagram),
-    Error,
+    BootNoise(usize),// Binary burst printed after _ok ESP_RESTART
 }
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+/// Shortest burst recognized as boot noise, shorter binary lines are just garbage.
+pub const BOOT_NOISE_MIN_LENGTH: usize = 32;
+
+/// Whether `byte` can be part of the boot noise.
+///
+/// The burst is the ESP32 boot log sent at half the station's baud rate: every bit lasts two
+/// samples, so apart from the start bit (bit 0) and the first half of the last data bit (bit 7),
+/// the bits of a received byte come in equal pairs. The stop bits and the idle time between
+/// bytes are not kept by the UART, so the log itself cannot be recovered.
+pub fn is_boot_noise_byte(byte: u8) -> bool {
+    let pairs = byte >> 1;
+    byte & 0x01 == 0 && (pairs ^ (pairs >> 1)) & 0b10101 == 0
+}
+
+/// Whether `line` (without its CR LF) is the boot noise.
+pub fn is_boot_noise(line: &[u8]) -> bool {
+    let printable = |b: &u8| b.is_ascii_graphic() || *b == b' ';
+    line.len() >= BOOT_NOISE_MIN_LENGTH && !line.iter().all(printable) && line.iter().all(|&b| is_boot_noise_byte(b))
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum AcknowledgeType {
     Alive,// ""
     RestartAP,// "ESP_RESTART"
@@ -170,6 +367,24 @@ pub enum AcknowledgeType {
     QuitConfig,// "QUIT_CONFIG"
 }
 
+impl AcknowledgeType {
+    /// Line sent by the UniStation for this acknowledge.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AcknowledgeType::Alive => "_ok",
+            AcknowledgeType::RestartAP => "_ok ESP_RESTART",
+            AcknowledgeType::StartWifi => "_ok WIFI_ON",
+            AcknowledgeType::QuitConfig => "_ok QUIT_CONFIG",
+        }
+    }
+}
+
+impl std::fmt::Display for AcknowledgeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub struct Quaternion {
     // bytes are sent as such:
@@ -188,7 +403,7 @@ impl From<[u8; 8]> for Quaternion {
         let z = value[4] as u16 + ((value[5] as u16) << 8);
         let x = value[6] as u16 + ((value[7] as u16) << 8);
 
-        Quaternion { x: x as i16, y: y as i16, z: -(z as i16), w: w as i16}
+        Quaternion { x: x as i16, y: y as i16, z: (z as i16).wrapping_neg(), w: w as i16}
     }
 }
 
@@ -200,308 +415,366 @@ pub struct Datagram {
     // A = ahrs enable = 1byte
     // M = magnetic level = 1byte
     // 
+    // The layout is picked from the decoded length, padding is optional.
+    //
     // b64 | decoded |
     // ------------------------------------
-    // 14  | 10.5    | IBQ
+    // 14  | 10      | IBQ
     // 16  | 12      | IBQAM
     // 24  | 18      | IBQQ
-    // 27  | 20.25   | IBQQAM
-    // 46  | 34.5    | IBQQQQ
+    // 27  | 20      | IBQQAM
+    // 46  | 34      | IBQQQQ
     // 48  | 36      | IBQQQQAM
-    // err | ???     | ???
+    // err | ???     | ParseError::UnsupportedDatagramLength
     // 
     pub id: u8, // [0]
     pub battery_voltage: u8, // [1]
     pub quaternions: [Option<Quaternion>; 4], // [2]
-    pub ahrs_enable: u8, // [10], [18], [34]
-    pub magnetic_power: u8, // [11], [19], [35]
+    // Only sent by the layouts ending in AM
+    pub ahrs_enable: Option<u8>, // [10], [18], [34]
+    pub magnetic_power: Option<u8>, // [11], [19], [35]
 }
 
-#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+impl Datagram {
+    /// Decode a base64 datagram, padded or not.
+    ///
+    /// The layout is picked from the number of decoded bytes.
+    pub fn decode(payload: &str) -> Result<Datagram, ParseError> {
+        match DATAGRAM_ENGINE.decode(payload) {
+            Ok(bytes) => Datagram::try_from(bytes.as_slice()),
+            Err(e) => Err(ParseError::InvalidBase64(e.to_string())),
+        }
+    }
+}
+
+/// Length of a `_si` payload carrying the magnetometer thresholds.
+pub const SENSOR_INFO_LENGTH: usize = 23;
+/// Length of a `_si` payload sent by firmwares without magnetometer thresholds.
+pub const SHORT_SENSOR_INFO_LENGTH: usize = 19;
+
+/// Settings reported by a sensor in `_si`.
+///
+/// Fields are decoded from the payload on access, `raw` gives the payload itself for the
+/// bytes nobody decoded yet.
+#[derive(Clone, Copy, Hash, Eq, PartialEq)]
 pub struct SensorInfo {
-    // SensorVersion may be unknown
-    sensor_version: u8, // [0]
-    mystery_value: u8, // [1]
-    // Note: This is the address of the station
-    mac_address: MacAddr6, // [2..8]
-    channel: u8,// [8]
-	tx_power: u8,// [9]
-	datamode: u8,// [11]
-	six_axis: bool,// [17]
-	imu_flip: bool,// [18]
-    // SensorInfo response may not contain Magnetic thresolds
-    min_mag_th: u8,// [21]
-    max_mag_th: u8,// [22]
+    bytes: [u8; SENSOR_INFO_LENGTH],
+    length: usize,
+}
+
+impl From<[u8; SHORT_SENSOR_INFO_LENGTH]> for SensorInfo {
+    fn from(value: [u8; SHORT_SENSOR_INFO_LENGTH]) -> Self {
+        let mut bytes = [0; SENSOR_INFO_LENGTH];
+        bytes[..SHORT_SENSOR_INFO_LENGTH].copy_from_slice(&value);
+        SensorInfo { bytes, length: SHORT_SENSOR_INFO_LENGTH }
+    }
+}
+
+impl From<[u8; SENSOR_INFO_LENGTH]> for SensorInfo {
+    fn from(value: [u8; SENSOR_INFO_LENGTH]) -> Self {
+        SensorInfo { bytes: value, length: SENSOR_INFO_LENGTH }
+    }
 }
 
-impl From<[u8; 19]> for SensorInfo {
-    fn from(value: [u8; 19]) -> Self {
-        // Minimum and maximum magnetic thresold are not transmitted
-        let sensor_version = value[0];
-        let mystery_value = value[1];
-        // MAC address is set to nil in case a TryFrom::Error is encontered
-        let mac_address = MacAddr6::from(value[2..8].try_into().unwrap_or([0; 6]));
-        let channel = value[8];
-        let tx_power  = value[9];
-        let datamode  = value[11];
-        let six_axis  = value[17] & 0x01 != 0;
-        let imu_flip  = value[18] & 0x01 != 0;
-
-        SensorInfo {
-            sensor_version: sensor_version,
-            mystery_value: mystery_value,
-            mac_address: mac_address,
-            channel: channel,
-            tx_power: tx_power,
-            datamode: datamode,
-            six_axis: six_axis,
-            imu_flip: imu_flip,
-            min_mag_th: 255,
-            max_mag_th: 255,
+impl TryFrom<&[u8]> for SensorInfo {
+    type Error = ParseError;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if let Ok(bytes) = <[u8; SENSOR_INFO_LENGTH]>::try_from(value) {
+            return Ok(SensorInfo::from(bytes));
+        }
+        match <[u8; SHORT_SENSOR_INFO_LENGTH]>::try_from(value) {
+            Ok(bytes) => Ok(SensorInfo::from(bytes)),
+            Err(_) => Err(ParseError::UnsupportedSensorInfoLength(value.len())),
         }
     }
 }
 
-impl From<[u8; 23]> for SensorInfo {
-    fn from(value: [u8; 23]) -> Self {
-        let sensor_version = value[0];
-        let mystery_value = value[1];
-        // MAC address is set to nil in case a TryFrom::Error is encontered
-        let mac_address = MacAddr6::from(value[2..8].try_into().unwrap_or([0; 6]));
-        let channel = value[8];
-        let tx_power  = value[9];
-        let datamode  = value[11];
-        let six_axis  = value[17] & 0x01 != 0;
-        let imu_flip  = value[18] & 0x01 != 0;
-        let min_mag_th  = value[21];
-        let max_mag_th  = value[22];
-
-        SensorInfo {
-            sensor_version: sensor_version,
-            mystery_value: mystery_value,
-            mac_address: mac_address,
-            channel: channel,
-            tx_power: tx_power,
-            datamode: datamode,
-            six_axis: six_axis,
-            imu_flip: imu_flip,
-            min_mag_th: min_mag_th,
-            max_mag_th: max_mag_th,
+impl SensorInfo {
+    /// The decoded `_si` payload, 19 or 23 bytes.
+    pub fn raw(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+
+    /// Firmware version, the values seen so far are not documented anywhere.
+    pub fn sensor_version(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    /// Byte 1 as received, its meaning (and whether it is a number at all) is unknown.
+    pub fn mystery_value(&self) -> &[u8] {
+        &self.bytes[1..2]
+    }
+
+    /// Address of the UniStation the sensor is paired with, not of the sensor itself.
+    pub fn mac_address(&self) -> MacAddr6 {
+        let addr: [u8; 6] = self.bytes[2..8].try_into().unwrap();
+        MacAddr6::from(addr)
+    }
+
+    /// WiFi channel.
+    pub fn channel(&self) -> u8 {
+        self.bytes[8]
+    }
+
+    /// Transmit power in dBm, sent by the ESP32 in steps of 0.25 dBm.
+    pub fn tx_power(&self) -> f32 {
+        self.bytes[9] as f32 * 0.25
+    }
+
+    /// Datamode, the first argument of `_setmode`.
+    pub fn datamode(&self) -> u8 {
+        self.bytes[11]
+    }
+
+    /// Mode the sensor streams in, `None` for a datamode no preset selects.
+    pub fn mode(&self) -> Option<SensorMode> {
+        SensorMode::from_datamode(self.datamode())
+    }
+
+    /// Whether the sensor runs without its magnetometer.
+    pub fn six_axis(&self) -> bool {
+        self.bytes[17] & 0x01 != 0
+    }
+
+    /// Whether the sensor is mounted upside down.
+    pub fn imu_flip(&self) -> bool {
+        self.bytes[18] & 0x01 != 0
+    }
+
+    /// Minimum and maximum magnetometer thresholds, `None` when the `_si` line did not carry them.
+    pub fn magnetic_thresholds(&self) -> Option<(u8, u8)> {
+        match self.length {
+            SENSOR_INFO_LENGTH => Some((self.bytes[21], self.bytes[22])),
+            _ => None,
         }
     }
+
+    pub fn min_magnetic_threshold(&self) -> Option<u8> {
+        self.magnetic_thresholds().map(|(min, _)| min)
+    }
+
+    pub fn max_magnetic_threshold(&self) -> Option<u8> {
+        self.magnetic_thresholds().map(|(_, max)| max)
+    }
+}
+
+impl std::fmt::Debug for SensorInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SensorInfo")
+            .field("sensor_version", &self.sensor_version())
+            .field("mystery_value", &self.mystery_value())
+            .field("mac_address", &self.mac_address())
+            .field("channel", &self.channel())
+            .field("tx_power", &self.tx_power())
+            .field("datamode", &self.datamode())
+            .field("six_axis", &self.six_axis())
+            .field("imu_flip", &self.imu_flip())
+            .field("magnetic_thresholds", &self.magnetic_thresholds())
+            .finish()
+    }
 }
 
 pub mod parsing {
     use super::*;
 
-    fn parse(line: &str) -> Result<Response, &str> {
+    fn parse(line: &str) -> Result<Response, ParseError> {
         let words: Vec<&str> = line.split(' ').collect();
 
-        let res = match (words[0], line.len()) {
-            ("_si", _) => {
-                let (dev, sensor_info) = parse_si(&line["_si".len()..line.len()].trim_start())?;
+        let res = match words[0] {
+            "" => {
+                return Err(ParseError::EmptyLine)
+            },
+            "_si" => {
+                let (dev, sensor_info) = parse_si(line["_si".len()..line.len()].trim_start())?;
                 Response::SensorInfo(dev, sensor_info)
             },
-            ("_dev", _) => {
-                let (dev, addr) = parse_dev(&line["_dev".len()..line.len()].trim_start())?;
+            "_dev" => {
+                let (dev, addr) = parse_dev(line["_dev".len()..line.len()].trim_start())?;
                 Response::Device(dev, addr)
             },
-            ("_ch", _) => {
-                let ch = parse_ch(&line["_ch".len()..line.len()].trim_start())?;
+            "_ch" => {
+                let ch = parse_ch(line["_ch".len()..line.len()].trim_start())?;
                 Response::Channel(ch)
             },
-            ("_auto_off", _) => {
-                let (enable, duration) = parse_auto_off(&line["_auto_off".len()..line.len()].trim_start())?;
+            "_auto_off" => {
+                let (enable, duration) = parse_auto_off(line["_auto_off".len()..line.len()].trim_start())?;
                 Response::AutoOff(enable, duration)
             },
-            ("_ok", _) => {
-                let ack = parse_ok(&line["_ok".len()..line.len()].trim_start())?;
+            "_ok" => {
+                let ack = parse_ok(line["_ok".len()..line.len()].trim_start())?;
                 Response::Acknowledge(ack)
             },
-            ("_datamode", _) => {
-                let dm = parse_datamode(&line["_datamode".len()..line.len()].trim_start())?;
+            "_datamode" => {
+                let dm = parse_datamode(line["_datamode".len()..line.len()].trim_start())?;
                 Response::Datamode(dm)
             },
-            // base64 encodes 6 bits for each byte (75%)
-            // TODO: Elimintate repeated code with a macro?
-            (_, 14) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(format!("{}==", line)) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 10]>>::parse_data(bytes[..10].try_into().unwrap())?;
-                Response::Data(data)
-            },
-            (_, 16) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(line) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 12]>>::parse_data(bytes[..12].try_into().unwrap())?;
-                Response::Data(data)
-            },
-            (_, 24) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(line) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 18]>>::parse_data(bytes[..18].try_into().unwrap())?;
-                Response::Data(data)
-            },
-            (_, 27) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(format!("{}=", line)) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 20]>>::parse_data(bytes[..20].try_into().unwrap())?;
-                Response::Data(data)
+            prefix if prefix.starts_with('_') => {
+                return Err(ParseError::UnknownPrefix(prefix.to_string()))
             },
-            (_, 46) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(format!("{}==", line)) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 34]>>::parse_data(bytes[..34].try_into().unwrap())?;
-                Response::Data(data)
-            },
-            (_, 48) => {
-                let Ok(bytes) = general_purpose::STANDARD.decode(line) else { return Err(line) };
-                let data = <Datagram as Parseable<[u8; 36]>>::parse_data(bytes[..36].try_into().unwrap())?;
-                Response::Data(data)
-            },
-            (_, _) => {
-                return Err(line)
+            _ => {
+                Response::Data(Datagram::decode(line)?)
             },
         };
         Ok(res)
     }
 
-    fn parse_si(line: &str) -> Result<(u8, SensorInfo), &str> {
+    fn fields(line: &str, expected: usize) -> Result<Vec<&str>, ParseError> {
         let v: Vec<&str> = line.split(' ').collect();
-        if v.len() != 2 {
-            return Err(line);
+        if v.len() != expected {
+            return Err(ParseError::WrongFieldCount { expected, got: v.len() });
         }
-        let Ok(id) = v[0].parse::<u8>() else { return Err(line) };
+        Ok(v)
+    }
 
-        let Ok(bytes) = general_purpose::STANDARD.decode(v[1]) else { return Err(line) };
-        match bytes.len() {
-            19 => {
-                let bytes: [u8; 19] = bytes[..19].try_into().unwrap();
-                Ok((id, SensorInfo::from(bytes)))
-            },
-            23 => {
-                let bytes: [u8; 23] = bytes[..23].try_into().unwrap();
-                Ok((id, SensorInfo::from(bytes)))
-            },
-            _ => {
-                Err(line)
-            }
-        }
+    fn parse_int<T: FromStr>(word: &str) -> Result<T, ParseError> {
+        word.parse::<T>().map_err(|_| ParseError::InvalidInteger(word.to_string()))
     }
 
-    fn parse_dev(line: &str) -> Result<(u8, MacAddr6), &str> {
-        let v: Vec<&str> = line.split(' ').collect();
-        if v.len() != 7 {
-            return Err(line);
-        }
-        let Ok(id) = v[0].parse::<u8>() else { return Err(line) };
-        // Padding values because the unistation return 0 0 0 0 0 0 when no unisensor is set.
-        let addr: &String = &v[1..7].iter().map(
-            |byte| if byte.len() < 2 { format!("0{}", byte) } else { byte.to_string() }
-        ).collect();
-        let Ok(addr) = MacAddr6::from_str(&addr) else { return Err(line) };
+    fn parse_si(line: &str) -> Result<(u8, SensorInfo), ParseError> {
+        let v = fields(line, 2)?;
+        let id = parse_int::<u8>(v[0])?;
 
-        Ok((id, addr))
+        let bytes = match general_purpose::STANDARD.decode(v[1]) {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(ParseError::InvalidBase64(e.to_string())),
+        };
+        Ok((id, SensorInfo::try_from(bytes.as_slice())?))
     }
 
-    fn parse_ch(line: &str) -> Result<u8, &str> {
-        match line.parse::<u8>() {
-            Ok(ch) => Ok(ch),
-            Err(e) => Err(line),
+    fn parse_dev(line: &str) -> Result<(u8, MacAddr6), ParseError> {
+        let v = fields(line, 7)?;
+        let id = parse_int::<u8>(v[0])?;
+        // Bytes are sent in hexadecimal without padding, the unistation returns 0 0 0 0 0 0 when no unisensor is set.
+        let mut addr = [0; 6];
+        for (byte, word) in addr.iter_mut().zip(&v[1..7]) {
+            *byte = u8::from_str_radix(word, 16).map_err(|_| ParseError::InvalidInteger(word.to_string()))?;
         }
+
+        Ok((id, MacAddr6::from(addr)))
     }
 
-    fn parse_auto_off(line: &str) -> Result<(u8, u64), &str> {
-        let v: Vec<&str> = line.split(' ').collect();
-        if v.len() != 2 {
-            return Err(line);
-        }
-        match (v[0].parse::<u8>(), v[1].parse::<u64>()) {
-            (Ok(a), Ok(b)) => Ok((a, b)),
-            (_, _) => Err(line),
-        }
+    fn parse_ch(line: &str) -> Result<u8, ParseError> {
+        parse_int::<u8>(line)
     }
 
-    fn parse_ok(line: &str) -> Result<AcknowledgeType, &str> {
+    fn parse_auto_off(line: &str) -> Result<(u8, u64), ParseError> {
+        let v = fields(line, 2)?;
+        Ok((parse_int::<u8>(v[0])?, parse_int::<u64>(v[1])?))
+    }
+
+    fn parse_ok(line: &str) -> Result<AcknowledgeType, ParseError> {
         match line {
             "" => Ok(AcknowledgeType::Alive),
             "ESP_RESTART" => Ok(AcknowledgeType::RestartAP),
             "WIFI_ON" => Ok(AcknowledgeType::StartWifi),
             "QUIT_CONFIG" => Ok(AcknowledgeType::QuitConfig),
-            _ => Err(line)
+            _ => Err(ParseError::UnknownAckToken(line.to_string()))
         }
     }
 
-    fn parse_datamode(line: &str) -> Result<u8, &str> {
-        match line.parse::<u8>() {
-            Ok(dm) => Ok(dm),
-            Err(e) => Err(line),
-        }
+    fn parse_datamode(line: &str) -> Result<u8, ParseError> {
+        parse_int::<u8>(line)
     }
 
     trait Parseable<T> {
-        fn parse_data<'a>(value: T) -> Result<Datagram, &'a str>;
+        fn parse_data(value: T) -> Datagram;
+    }
+
+    /// Split a datagram into id, battery level, `quaternion_count` quaternions,
+    /// and the ahrs/magnetic bytes when `extended` is set.
+    fn parse_layout(value: &[u8], quaternion_count: usize, extended: bool) -> Datagram {
+        let id = value[0];
+        let battery_voltage = value[1];
+        let mut quaternions = [None; 4];
+        for (i, quaternion) in quaternions.iter_mut().take(quaternion_count).enumerate() {
+            let q: [u8; 8] = value[2 + 8 * i..10 + 8 * i].try_into().unwrap();
+            *quaternion = Some(Quaternion::from(q));
+        }
+        let tail = 2 + 8 * quaternion_count;
+        let (ahrs_enable, magnetic_power) = match extended {
+            true => (Some(value[tail]), Some(value[tail + 1])),
+            false => (None, None),
+        };
+
+        Datagram {
+            id,
+            battery_voltage,
+            quaternions,
+            ahrs_enable,
+            magnetic_power,
+        }
     }
-    
+
+    // IBQ
     impl Parseable<[u8; 10]> for Datagram {
-        fn parse_data<'a>(value: [u8; 10]) -> Result<Datagram, &'a str> {
-            Err("Not implemented")
+        fn parse_data(value: [u8; 10]) -> Datagram {
+            parse_layout(&value, 1, false)
         }
     }
 
+    // IBQAM
     impl Parseable<[u8; 12]> for Datagram {
-        fn parse_data<'a>(value: [u8; 12]) -> Result<Datagram, &'a str> {
-            Err("Not implemented")
+        fn parse_data(value: [u8; 12]) -> Datagram {
+            parse_layout(&value, 1, true)
         }
     }
 
+    // IBQQ
     impl Parseable<[u8; 18]> for Datagram {
-        fn parse_data<'a>(value: [u8; 18]) -> Result<Datagram, &'a str> {
-            Err("Not implemented")
+        fn parse_data(value: [u8; 18]) -> Datagram {
+            parse_layout(&value, 2, false)
         }
     }
 
+    // IBQQAM
     impl Parseable<[u8; 20]> for Datagram {
-        fn parse_data<'a>(value: [u8; 20]) -> Result<Datagram, &'a str> {
-            let id = value[0];
-            let battery_voltage = value[1];
-            let q1: [u8; 8] = value[2..10].try_into().unwrap();
-            let q2: [u8; 8] = value[10..18].try_into().unwrap();
-            let quaternions = [
-                Some(Quaternion::from(q1)),
-                Some(Quaternion::from(q2)),
-                None,
-                None,
-            ];
-            let ahrs_enable = value[18];
-            let magnetic_power = value[19];
-
-            Ok(Datagram {
-                id: id,
-                battery_voltage: battery_voltage,
-                quaternions: quaternions,
-                ahrs_enable: ahrs_enable,
-                magnetic_power: magnetic_power,
-            })
+        fn parse_data(value: [u8; 20]) -> Datagram {
+            parse_layout(&value, 2, true)
         }
     }
 
+    // IBQQQQ
     impl Parseable<[u8; 34]> for Datagram {
-        fn parse_data<'a>(value: [u8; 34]) -> Result<Datagram, &'a str> {
-            Err("Not implemented")
+        fn parse_data(value: [u8; 34]) -> Datagram {
+            parse_layout(&value, 4, false)
         }
     }
 
+    // IBQQQQAM
     impl Parseable<[u8; 36]> for Datagram {
-        fn parse_data<'a>(value: [u8; 36]) -> Result<Datagram, &'a str> {
-            Err("Not implemented")
+        fn parse_data(value: [u8; 36]) -> Datagram {
+            parse_layout(&value, 4, true)
         }
     }
 
-    impl From<Vec<u8>> for Response {
-        fn from(buffer: Vec<u8>) -> Self {
-            let line = String::from_utf8_lossy(&buffer);
-            let line = &line.trim();
-            match parse(&line) {
-                Ok(res) => res,
-                Err(_) => Response::Error,
+    impl TryFrom<&[u8]> for Datagram {
+        type Error = ParseError;
+
+        fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+            let data = match value.len() {
+                10 => <Datagram as Parseable<[u8; 10]>>::parse_data(value.try_into().unwrap()),
+                12 => <Datagram as Parseable<[u8; 12]>>::parse_data(value.try_into().unwrap()),
+                18 => <Datagram as Parseable<[u8; 18]>>::parse_data(value.try_into().unwrap()),
+                20 => <Datagram as Parseable<[u8; 20]>>::parse_data(value.try_into().unwrap()),
+                34 => <Datagram as Parseable<[u8; 34]>>::parse_data(value.try_into().unwrap()),
+                36 => <Datagram as Parseable<[u8; 36]>>::parse_data(value.try_into().unwrap()),
+                n => return Err(ParseError::UnsupportedDatagramLength(n)),
+            };
+            Ok(data)
+        }
+    }
+
+    impl TryFrom<&[u8]> for Response {
+        type Error = ParseError;
+
+        fn try_from(buffer: &[u8]) -> Result<Self, Self::Error> {
+            let bytes = buffer.strip_suffix(b"\r\n").unwrap_or(buffer);
+            if is_boot_noise(bytes) {
+                return Ok(Response::BootNoise(bytes.len()));
             }
-            
+            let line = String::from_utf8_lossy(buffer);
+            parse(line.trim())
         }
     }
 }
\ No newline at end of file
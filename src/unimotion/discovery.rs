@@ -0,0 +1,125 @@
+use super::*;
+use manager::{Command, UnimotionManagerBuilder, UnimotionSerialNumber};
+use device::{Response, AcknowledgeType};
+
+use std::io::{BufRead, BufReader};
+use std::time::Instant;
+
+use serialport::{SerialPortType, UsbPortInfo};
+
+/// USB vendor ID of the UniStation's USB-UART bridge (Silicon Labs CP210x).
+pub const UNISTATION_VENDOR_ID: u16 = 0x10c4;
+/// USB product ID of the UniStation's USB-UART bridge (Silicon Labs CP210x).
+pub const UNISTATION_PRODUCT_ID: u16 = 0xea60;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usb_info(vid: u16, pid: u16) -> UsbPortInfo {
+        UsbPortInfo {
+            vid,
+            pid,
+            serial_number: Some(String::from("0001")),
+            manufacturer: None,
+            product: None,
+        }
+    }
+
+    #[test]
+    fn test_check_usb_ids() {
+        assert!(check_usb_ids(&usb_info(UNISTATION_VENDOR_ID, UNISTATION_PRODUCT_ID)).is_ok());
+        assert!(matches!(
+            check_usb_ids(&usb_info(0x1a86, UNISTATION_PRODUCT_ID)),
+            Err(UnimotionDeviceError::InvalidVendorID(0x1a86))
+        ));
+        assert!(matches!(
+            check_usb_ids(&usb_info(UNISTATION_VENDOR_ID, 0x7523)),
+            Err(UnimotionDeviceError::InvalidProductID(0x7523))
+        ));
+    }
+}
+
+/// A serial port that answered `_alive` like a UniStation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DiscoveredStation {
+    pub port_path: String,
+    pub serial_number: Option<UnimotionSerialNumber>,
+}
+
+/// Check that a USB serial port has the UniStation's vendor and product IDs.
+pub fn check_usb_ids(info: &UsbPortInfo) -> Result<(), UnimotionDeviceError> {
+    if info.vid != UNISTATION_VENDOR_ID {
+        return Err(UnimotionDeviceError::InvalidVendorID(info.vid));
+    }
+    if info.pid != UNISTATION_PRODUCT_ID {
+        return Err(UnimotionDeviceError::InvalidProductID(info.pid));
+    }
+    Ok(())
+}
+
+/// Find every connected UniStation, using the default `UnimotionManagerBuilder` settings to probe them.
+pub fn discover_stations() -> UnimotionResult<Vec<DiscoveredStation>> {
+    discover_stations_with(&UnimotionManagerBuilder::default())
+}
+
+/// Find every connected UniStation.
+///
+/// USB serial ports are filtered by VID/PID, then each candidate is sent `_alive` and
+/// only kept if it acknowledges within the builder's acknowledge timeout.
+/// Ports that cannot be opened (e.g. already in use) are skipped.
+pub fn discover_stations_with(builder: &UnimotionManagerBuilder) -> UnimotionResult<Vec<DiscoveredStation>> {
+    let mut stations = Vec::new();
+    for port in serialport::available_ports()? {
+        let SerialPortType::UsbPort(info) = port.port_type else { continue };
+        if check_usb_ids(&info).is_err() {
+            continue;
+        }
+        if let Ok(true) = probe(&port.port_name, builder) {
+            stations.push(DiscoveredStation {
+                port_path: port.port_name,
+                serial_number: info.serial_number.map(UnimotionSerialNumber),
+            });
+        }
+    }
+    Ok(stations)
+}
+
+/// Port the UniStation with USB serial number `serial_number` is currently connected to.
+///
+/// The port path can change when the dongle is plugged in again, its serial number does not.
+pub fn find_station_port(serial_number: &UnimotionSerialNumber) -> UnimotionResult<String> {
+    for port in serialport::available_ports()? {
+        let SerialPortType::UsbPort(info) = port.port_type else { continue };
+        if check_usb_ids(&info).is_ok() && info.serial_number.as_deref() == Some(serial_number.0.as_str()) {
+            return Ok(port.port_name);
+        }
+    }
+    Err(UnimotionDeviceError::StationNotFound(serial_number.clone()).into())
+}
+
+/// Send `_alive` on `port_path` and wait for the UniStation's `_ok`.
+fn probe(port_path: &str, builder: &UnimotionManagerBuilder) -> UnimotionResult<bool> {
+    let mut port = serialport::new(port_path, builder.baud_rate)
+        .timeout(builder.ack_timeout)
+        .open()?;
+    port.write_all(&Command::Alive.encode())?;
+
+    let deadline = Instant::now() + builder.ack_timeout;
+    let mut reader = BufReader::new(port);
+    let mut buffer = Vec::new();
+    while Instant::now() < deadline {
+        buffer.clear();
+        match reader.read_until(b'\n', &mut buffer) {
+            Ok(0) => return Ok(false),
+            Ok(_) => {
+                if let Ok(Response::Acknowledge(AcknowledgeType::Alive)) = Response::try_from(buffer.as_slice()) {
+                    return Ok(true);
+                }
+            },
+            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => return Ok(false),
+            Err(e) => return Err(UnimotionError::from(e)),
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,23 @@
+//! Things the UniStation reports on its own, outside of the command/response flow.
+use super::*;
+use device::Response;
+use state::StationState;
+use macaddr::MacAddr6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The station printed its boot noise, `length` bytes of it.
+    StationBooted { length: usize },
+    /// The station restarted while streaming, every sensor setting is back to its default.
+    StationReset,
+    /// The station moved from one lifecycle state to another, see `UnimotionManager::state`.
+    StateChanged { from: StationState, to: StationState },
+    /// A response nobody was waiting for, e.g. a late reply to a request that timed out.
+    Unsolicited(Response),
+    /// A sensor was paired in an empty slot, see `UnimotionManager::refresh_sensor_list`.
+    SensorAdded { id: u8, addr: MacAddr6 },
+    /// The sensor in slot `id` was unpaired.
+    SensorRemoved { id: u8, addr: MacAddr6 },
+    /// Another sensor was paired in slot `id`.
+    SensorReplaced { id: u8, old: MacAddr6, new: MacAddr6 },
+}
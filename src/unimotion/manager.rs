@@ -3,73 +3,622 @@ use self::device::UniSensorDevice;
 use super::*;
 use macaddr::MacAddr6;
 use device::{SensorInfo, Response, Datagram, AcknowledgeType};
+use discovery::DiscoveredStation;
 
-use std::io::BufRead;
 use std::option::Option::Some;
-use std::sync::{Mutex, Once};
-use std::time::Duration;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use std::thread::JoinHandle;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use serialport::SerialPort;
+use crossbeam_channel::select;
+
+use transport::{Transport, TransportReader, Connector, SerialTransport};
+use decoder::ResponseDecoder;
+use protocol::{CommandSpec, ReplyKind};
+use event::Event;
+use mode::SensorMode;
+use pairing::PairingSession;
+use scheduler::{Scheduler, SchedulerConfig, Confirmation, CommandHandle};
+use state::{SharedStation, StationMachine, StationState};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sim::{Simulator, PAIRED_SENSORS};
+    use testing::{start, start_with, sync, streaming_sensors, next_fault, TIMEOUT};
+    use transport::pipe;
+
+    /// Every command, addressed to `id` where it takes one.
+    fn all_commands(target: SensorTarget, min: u8, max: u8) -> Vec<Command> {
+        vec![
+            Command::RestartAP,
+            Command::Alive,
+            Command::ListSensor,
+            Command::StartWifi,
+            Command::QuitConfig,
+            Command::RequestSensorInfo(target.clone()),
+            Command::AliveNoResponse,
+            Command::EnableAhrs(target.clone()),
+            Command::DisableAhrs(target.clone()),
+            Command::SetMode(target.clone(), SensorMode::FPS_60),
+            Command::SetMode(target.clone(), SensorMode::FPS_60_LOW_POWER),
+            Command::SetMode(target.clone(), SensorMode::FPS_70),
+            Command::SetMode(target.clone(), SensorMode::FPS_144),
+            Command::PowerOffSensor(target.clone()),
+            Command::RestartSensor(target.clone()),
+            Command::StartMagneticCalibration(target.clone()),
+            Command::StopMagneticCalibration(target.clone()),
+            Command::SetMagneticThreshold(target.clone(), min, max),
+            Command::SensorConfig(target.clone()),
+            Command::Config(target.clone()),
+            Command::InitializeCalibration(target.clone()),
+            Command::SavePairing,
+        ]
+    }
+
+    #[test]
+    fn test_command_round_trip() {
+        // xorshift32 over every byte value, plus the edges
+        let mut seed: u32 = 0x1234_5678;
+        let mut values = vec![0, 1, 23, 24, 255];
+        for _ in 0..64 {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            values.push(seed as u8);
+        }
+
+        for window in values.windows(3) {
+            for cmd in all_commands(window[0].into(), window[1], window[2]) {
+                assert_eq!(Command::from_str(&cmd.as_str()), Ok(cmd));
+            }
+        }
+    }
+
+    #[test]
+    fn test_command_from_str() {
+        for line in [
+            "_aprestart",
+            "_setmode id:3:b 2 4 30 4",
+            "_set_mag_th id:1:b 0 124",
+            "_setahrsmode id:7:b 1",
+            "__sensinfo id:255:b",
+            "_restart id:4:b",
+            "_initcalibration id:2:b",
+        ] {
+            assert_eq!(Command::from_str(line).map(|cmd| cmd.as_str()), Ok(String::from(line)));
+        }
+        assert_eq!(Command::from_str("_setmode id:3:b 2 4 30 4"), Ok(Command::SetMode(SensorTarget::One(3), SensorMode::FPS_144)));
+        assert_eq!(Command::from_str("_config id:5:b"), Ok(Command::Config(SensorTarget::One(5))));
+        assert_eq!(Command::from_str("_sensoff id:255:b"), Ok(Command::PowerOffSensor(SensorTarget::All)));
+    }
+
+    #[test]
+    fn test_command_expand() {
+        let cmd = Command::SetMagneticThreshold(SensorTarget::Set(vec![1, 4]), 0, 124);
+        assert_eq!(cmd.encode(), b"_set_mag_th id:1:b 0 124\n_set_mag_th id:4:b 0 124\n");
+        assert_eq!(cmd.expand(), [
+            Command::SetMagneticThreshold(SensorTarget::One(1), 0, 124),
+            Command::SetMagneticThreshold(SensorTarget::One(4), 0, 124),
+        ]);
+        assert_eq!(Command::SetMode(SensorTarget::All, SensorMode::FPS_144).encode(), b"_setmode id:255:b 2 4 30 4\n");
+        assert!(Command::PowerOffSensor(SensorTarget::Set(Vec::new())).encode().is_empty());
+    }
+
+    #[test]
+    fn test_command_errors() {
+        assert_eq!(Command::from_str(""), Err(ParseError::EmptyLine));
+        assert_eq!(Command::from_str("_reboot"), Err(ParseError::UnknownPrefix(String::from("_reboot"))));
+        assert_eq!(Command::from_str("_alive id:1:b"), Err(ParseError::WrongFieldCount { expected: 1, got: 2 }));
+        assert_eq!(Command::from_str("_sensoff"), Err(ParseError::WrongFieldCount { expected: 2, got: 1 }));
+        assert_eq!(Command::from_str("_sensoff 3"), Err(ParseError::InvalidTarget(String::from("3"))));
+        assert_eq!(Command::from_str("_sensoff id:300:b"), Err(ParseError::InvalidInteger(String::from("300"))));
+        assert_eq!(Command::from_str("_set_mag_th id:1:b 0"), Err(ParseError::WrongFieldCount { expected: 4, got: 3 }));
+        assert_eq!(Command::from_str("_setmode id:1:b 1 2 3 4"), Err(ParseError::UnknownArguments(String::from("1 2 3 4"))));
+        assert_eq!(Command::from_str("_setahrsmode id:1:b 2"), Err(ParseError::UnknownArguments(String::from("2"))));
+        // `Restart` used to be sent without the `:b`
+        assert_eq!(Command::from_str("_restart id:4"), Err(ParseError::InvalidTarget(String::from("id:4"))));
+    }
+
+    #[test]
+    fn test_set_mode() {
+        let (mut manager, sim) = start();
+        manager.set_mode(SensorTarget::One(0), SensorMode::FPS_144).unwrap();
+        sync(&mut manager);
+
+        let mut counts = [0; MAX_UNISENSOR_COUNT];
+        for _ in 0..400 {
+            let (device, _) = manager.update().unwrap();
+            counts[device.id as usize] += 1;
+        }
+        assert!(counts[0] > counts[1] * 3 / 2, "{:?}", counts);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_unpaired_sensor() {
+        let (mut manager, sim) = start();
+        assert!(matches!(
+            manager.send_command(Command::PowerOffSensor(SensorTarget::One(20))),
+            Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorNotPaired(20)))
+        ));
+        assert!(matches!(
+            manager.request_sensor_info(30),
+            Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorIdOutOfRange(30)))
+        ));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_sensor_targets() {
+        let (mut manager, sim) = start();
+        // Nothing is sent if any sensor of the set is invalid
+        assert!(matches!(
+            manager.send_command(Command::PowerOffSensor(SensorTarget::Set(vec![1, 30]))),
+            Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorIdOutOfRange(30)))
+        ));
+
+        manager.send_command(Command::PowerOffSensor(SensorTarget::Set(vec![1, 2]))).unwrap();
+        sync(&mut manager);
+        assert_eq!(streaming_sensors(&manager, 100), [0, 3, 4, 5, 6, 7]);
+
+        manager.send_command(Command::PowerOffSensor(SensorTarget::All)).unwrap();
+        sync(&mut manager);
+        assert!(manager.data_receiver().recv_timeout(Duration::from_millis(200)).is_err());
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_send_and_wait() {
+        let (mut manager, sim) = start();
+        assert_eq!(manager.send_and_wait(Command::Alive, TIMEOUT).unwrap(), Reply::Ack(AcknowledgeType::Alive));
+
+        match manager.send_and_wait(Command::ListSensor, TIMEOUT).unwrap() {
+            Reply::SensorList(devices) => {
+                assert_eq!(devices.len(), MAX_UNISENSOR_COUNT);
+                assert_eq!(devices[7], (7, MacAddr6::from_str("AC:0B:FB:C5:4F:A5").unwrap()));
+            },
+            r => panic!("{:?}", r),
+        }
+
+        let ids = |reply| match reply {
+            Reply::SensorInfo(infos) => infos.into_iter().map(|(id, _)| id).collect::<Vec<_>>(),
+            r => panic!("{:?}", r),
+        };
+        let set = Command::RequestSensorInfo(SensorTarget::Set(vec![1, 3]));
+        assert_eq!(ids(manager.send_and_wait(set, TIMEOUT).unwrap()), [1, 3]);
+        let all = Command::RequestSensorInfo(SensorTarget::All);
+        assert_eq!(ids(manager.send_and_wait(all, TIMEOUT).unwrap()), [0, 1, 2, 3, 4, 5, 6, 7]);
+        // Last, the second `_si` could answer a later request
+        let twice = Command::RequestSensorInfo(SensorTarget::Set(vec![1, 1]));
+        assert_eq!(ids(manager.send_and_wait(twice, TIMEOUT).unwrap()), [1]);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_stale_replies() {
+        let (mut manager, sim) = start();
+        while manager.events().try_recv().is_ok() {}
+
+        // Replies to requests nobody waits for must not answer the next ones
+        manager.send_command(Command::Alive).unwrap();
+        manager.send_command(Command::RequestSensorInfo(SensorTarget::One(2))).unwrap();
+        sync(&mut manager);
+
+        let reply = manager.send_and_wait(Command::RequestSensorInfo(SensorTarget::One(4)), TIMEOUT).unwrap();
+        assert!(matches!(reply, Reply::SensorInfo(ref infos) if infos.len() == 1 && infos[0].0 == 4));
+        let reply = manager.send_and_wait(Command::StartWifi, TIMEOUT).unwrap();
+        assert_eq!(reply, Reply::Ack(AcknowledgeType::StartWifi));
+
+        let events: Vec<_> = manager.events().try_iter().collect();
+        assert!(matches!(events[..], [
+            Event::Unsolicited(Response::SensorInfo(2, _)),
+            Event::Unsolicited(Response::Acknowledge(AcknowledgeType::Alive)),
+        ]));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_sensor_not_responding() {
+        let (mut manager, sim) = start();
+        assert!(manager.request_sensor_info(3).is_ok());
+
+        manager.send_command(Command::PowerOffSensor(SensorTarget::One(3))).unwrap();
+        assert!(matches!(
+            manager.request_sensor_info(3),
+            Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorNotResponding(3)))
+        ));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_get_sensor_info() {
+        let (mut manager, sim) = start();
+        while manager.events().try_recv().is_ok() {}
+
+        manager.send_command(Command::RequestSensorInfo(SensorTarget::One(3))).unwrap();
+        let (id, info) = manager.get_sensor_info(None).unwrap();
+        assert_eq!(id, 3);
+        assert_eq!(Some(info), manager.sensors()[3].sensor_info);
+
+        // Other sensors' lines are not lost
+        manager.send_command(Command::RequestSensorInfo(SensorTarget::Set(vec![2, 4]))).unwrap();
+        assert_eq!(manager.get_sensor_info_timeout(Some(4), TIMEOUT).unwrap().0, 4);
+        assert!(matches!(manager.events().try_recv(), Ok(Event::Unsolicited(Response::SensorInfo(2, _)))));
+        assert!(matches!(
+            manager.get_sensor_info_timeout(Some(4), Duration::from_millis(100)),
+            Err(UnimotionError::Timeout { waiting_for: "_si" })
+        ));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let (mut manager, sim) = start();
+        sim.inject(b"_foo 1\r\n");
+        assert_eq!(
+            manager.parse_errors().recv_timeout(TIMEOUT),
+            Ok(ParseError::UnknownPrefix(String::from("_foo")))
+        );
+
+        // Unread ones are capped, the station keeps being served
+        for _ in 0..PARSE_ERROR_CAPACITY + 10 {
+            sim.inject(b"_foo 1\r\n");
+        }
+        sync(&mut manager);
+        assert_eq!(manager.parse_errors().try_iter().count(), PARSE_ERROR_CAPACITY);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_sensor_info_refresh() {
+        let (mut manager, sim) = start();
+        manager.set_mode(SensorTarget::One(2), SensorMode::FPS_144).unwrap();
+        // The `_si` confirming the mode is enough
+        let info = manager.sensors()[2].sensor_info.unwrap();
+        assert_eq!(info.mode(), Some(SensorMode::FPS_144));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_refresh_sensor_list() {
+        let (mut manager, sim) = start();
+        assert!(manager.refresh_sensor_list().unwrap().is_empty());
+        while manager.events().try_recv().is_ok() {}
+
+        let old = MacAddr6::from_str(PAIRED_SENSORS[2]).unwrap();
+        let new = MacAddr6::from_str("E8:68:E7:53:56:01").unwrap();
+        sim.pair(1, MacAddr6::nil());
+        sim.pair(2, new);
+        sim.pair(9, new);
+        let expected = [
+            Event::SensorRemoved { id: 1, addr: MacAddr6::from_str(PAIRED_SENSORS[1]).unwrap() },
+            Event::SensorReplaced { id: 2, old, new },
+            Event::SensorAdded { id: 9, addr: new },
+        ];
+        assert_eq!(manager.refresh_sensor_list().unwrap(), expected);
+        assert_eq!(manager.events().try_iter().collect::<Vec<_>>(), expected);
+
+        let ids: Vec<u8> = manager.sensors().iter().map(|sensor| sensor.id).collect();
+        assert_eq!(ids, [0, 2, 3, 4, 5, 6, 7, 9]);
+        assert!(matches!(
+            manager.send_command(Command::RestartSensor(SensorTarget::One(1))),
+            Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorNotPaired(1)))
+        ));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_reconnect() {
+        // Every connection is a freshly plugged station, back to its default mode
+        let sims = Arc::new(Mutex::new(Vec::new()));
+        let connector = {
+            let sims = sims.clone();
+            move || -> UnimotionResult<Box<dyn Transport>> {
+                let (local, remote) = pipe(Duration::from_millis(20));
+                sims.lock().unwrap().push(Simulator::new().spawn(Box::new(remote))?);
+                Ok(Box::new(local))
+            }
+        };
+        let builder = UnimotionManagerBuilder::new().reconnect_interval(Duration::from_millis(50));
+        let mut manager = builder.build_with_connector(connector).unwrap();
+        manager.set_mode(SensorTarget::One(1), SensorMode::FPS_144).unwrap();
+
+        // Unplugged: the datagrams already received come first
+        let unplugged = sims.lock().unwrap().remove(0);
+        unplugged.stop().unwrap();
+        assert!(matches!(next_fault(&mut manager), UnimotionError::Disconnected));
+        assert_eq!(manager.state(), StationState::Disconnected);
+
+        assert!(manager.update().is_ok());
+        assert_eq!(manager.state(), StationState::Streaming);
+        assert_eq!(sims.lock().unwrap().len(), 1);
+        assert_eq!(manager.request_sensor_info(1).unwrap().mode(), Some(SensorMode::FPS_144));
+        assert_eq!(manager.request_sensor_info(2).unwrap().mode(), Some(SensorMode::FPS_60));
+
+        drop(manager);
+        for sim in sims.lock().unwrap().drain(..) {
+            sim.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reconnect_after_failed_handshake() {
+        // The first station plugged back in never answers
+        let sims = Arc::new(Mutex::new(Vec::new()));
+        let silent = Arc::new(Mutex::new(Vec::new()));
+        let connector = {
+            let (sims, silent) = (sims.clone(), silent.clone());
+            let mut connections = 0;
+            move || -> UnimotionResult<Box<dyn Transport>> {
+                let (local, remote) = pipe(Duration::from_millis(20));
+                connections += 1;
+                match connections {
+                    2 => silent.lock().unwrap().push(remote),
+                    _ => sims.lock().unwrap().push(Simulator::new().spawn(Box::new(remote))?),
+                }
+                Ok(Box::new(local))
+            }
+        };
+        let builder = UnimotionManagerBuilder::new()
+            .reconnect_interval(Duration::from_millis(50))
+            .ack_timeout(Duration::from_millis(100));
+        let mut manager = builder.build_with_connector(connector).unwrap();
+        manager.set_mode(SensorTarget::One(1), SensorMode::FPS_144).unwrap();
+
+        let unplugged = sims.lock().unwrap().remove(0);
+        unplugged.stop().unwrap();
+        assert!(matches!(next_fault(&mut manager), UnimotionError::Disconnected));
+        assert!(matches!(manager.update(), Err(UnimotionError::Timeout { .. })));
+        assert_eq!(manager.state(), StationState::Disconnected);
+
+        assert!(manager.update().is_ok());
+        assert_eq!(manager.state(), StationState::Streaming);
+        assert_eq!(manager.request_sensor_info(1).unwrap().mode(), Some(SensorMode::FPS_144));
+
+        drop(manager);
+        for sim in sims.lock().unwrap().drain(..) {
+            sim.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reconnect_without_connector() {
+        let (mut manager, sim) = start();
+        sim.stop().unwrap();
+        assert!(matches!(next_fault(&mut manager), UnimotionError::Disconnected));
+        assert!(matches!(manager.update(), Err(UnimotionError::Disconnected)));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        // Managers can be opened and closed over and over
+        for action in [ShutdownAction::Nothing, ShutdownAction::PowerOffSensors, ShutdownAction::Restart] {
+            let (mut manager, sim) = start_with(Simulator::new(), UnimotionManagerBuilder::new().on_shutdown(action));
+            assert!(manager.update().is_ok());
+            manager.shutdown().unwrap();
+            sim.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_drop_stops_ingress() {
+        let (manager, sim) = start();
+        let events = manager.events().clone();
+        drop(manager);
+        // The ingress thread owned the last event sender, it is gone once the drop returns
+        while events.try_recv().is_ok() {}
+        assert_eq!(events.try_recv(), Err(crossbeam_channel::TryRecvError::Disconnected));
+        sim.join().unwrap();
+    }
+}
 
 pub const MAX_UNISENSOR_COUNT: usize = 24;
 
-// #[cfg_attr(feature = "use_serde", derive(Serialize, Deserialize))]
-// #[derive(Copy, Clone, Hash, PartialEq, Eq, Debug)]
+/// Serial port the UniStation shows up as when no other USB-serial adapter is plugged in.
+pub const DEFAULT_PORT_PATH: &str = "/dev/ttyUSB0";
+/// Baud rate used by the UniStation firmware.
+pub const DEFAULT_BAUD_RATE: u32 = 230_400;
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_millis(1000);
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_millis(500);
+pub const DEFAULT_REPORT_TIMEOUT: Duration = Duration::from_millis(500);
+pub const DEFAULT_SENSOR_TIMEOUT: Duration = Duration::from_millis(1000);
+pub const DEFAULT_COMMAND_INTERVAL: Duration = Duration::from_millis(10);
+pub const DEFAULT_COMMAND_ATTEMPTS: u32 = 4;
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(50);
+pub const DEFAULT_RECONNECT_INTERVAL: Duration = Duration::from_millis(1000);
+/// Parse errors kept until read, newer ones are dropped past that.
+pub const PARSE_ERROR_CAPACITY: usize = 64;
+
+/// Id the UniStation reads as "every sensor".
+pub const BROADCAST_ID: u8 = 255;
+
+/// What `UnimotionManager::shutdown` (or dropping the manager) does before closing the port.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug, Default)]
+pub enum ShutdownAction {
+    /// Leave the station and the sensors streaming.
+    #[default]
+    Nothing,
+    /// Switch every sensor off, the station keeps running.
+    PowerOffSensors,
+    /// Restart the station, which then waits in config mode without streaming.
+    Restart,
+}
+
+/// Sensors a per-sensor command is addressed to.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
+pub enum SensorTarget {
+    One(u8),
+    /// Every sensor, sent once with the broadcast id.
+    All,
+    /// Several sensors, sent once per sensor.
+    Set(Vec<u8>),
+}
+
+impl From<u8> for SensorTarget {
+    fn from(id: u8) -> Self {
+        match id {
+            BROADCAST_ID => SensorTarget::All,
+            id => SensorTarget::One(id),
+        }
+    }
+}
+
+impl SensorTarget {
+    /// Whether the sensor in slot `id` is addressed.
+    pub fn contains(&self, id: u8) -> bool {
+        match self {
+            SensorTarget::One(one) => *one == id,
+            SensorTarget::All => true,
+            SensorTarget::Set(ids) => ids.contains(&id),
+        }
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Hash, PartialEq, Eq, Debug)]
 pub enum Command {
     RestartAP,
     Alive,
     ListSensor,
     StartWifi,
     QuitConfig,
-    RequestSensorInfo(u8),
+    RequestSensorInfo(SensorTarget),
     AliveNoResponse,
-    EnableAhrs(u8),
-    DisableAhrs(u8),
-    Set60FPS(u8),
-    Set60FPSLowPower(u8),
-    Set70FPS(u8),
-    Set144FPS(u8),
-    PowerOffSensor(u8),
-    RestartSensor(u8),
-    StartMagneticCalibration(u8),
-    StopMagneticCalibration(u8),
-    SetMagneticThreshold(u8, u8, u8),
-    SensorConfig(u8),
-    Config(u8),
-    InitializeCalibration(u8),
-    Restart(u8),
+    EnableAhrs(SensorTarget),
+    DisableAhrs(SensorTarget),
+    SetMode(SensorTarget, SensorMode),
+    PowerOffSensor(SensorTarget),
+    RestartSensor(SensorTarget),
+    StartMagneticCalibration(SensorTarget),
+    StopMagneticCalibration(SensorTarget),
+    SetMagneticThreshold(SensorTarget, u8, u8),
+    SensorConfig(SensorTarget),
+    Config(SensorTarget),
+    InitializeCalibration(SensorTarget),
     SavePairing,
 }
 
 impl Command {
+    /// Line sent to the UniStation, without the line terminator.
+    ///
+    /// A command addressed to a `SensorTarget::Set` is one line per sensor, separated by `\n`.
     pub fn as_str(&self) -> String {
+        self.expand().iter().map(protocol::encode).collect::<Vec<_>>().join("\n")
+    }
+
+    /// Bytes written to the UniStation.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for cmd in self.expand() {
+            bytes.extend(protocol::encode(&cmd).into_bytes());
+            bytes.push(b'\n');
+        }
+        bytes
+    }
+
+    /// Entry of the protocol table describing this command.
+    pub fn spec(&self) -> &'static CommandSpec {
+        protocol::spec(self)
+    }
+
+    /// What the UniStation answers to this command.
+    pub fn reply_kind(&self) -> ReplyKind {
+        self.spec().reply
+    }
+
+    /// Sensors the command is addressed to, `None` for station commands.
+    pub fn target(&self) -> Option<&SensorTarget> {
         match self {
-            Command::RestartAP => String::from("_aprestart"),
-            Command::Alive => String::from("_alive"),
-            Command::ListSensor => String::from("_sensorlist"),
-            Command::StartWifi => String::from("_wifistart"),
-            Command::QuitConfig => String::from("_quitconfig"),
-            Command::RequestSensorInfo(id) => format!("__sensinfo id:{id}:b"),
-            Command::AliveNoResponse => String::from("_alive_nores"),
-            Command::EnableAhrs(id) => format!("_setahrsmode id:{id}:b 0"),
-            Command::DisableAhrs(id) => format!("_setahrsmode id:{id}:b 1"),
-            Command::Set60FPS(id) => format!("_setmode id:{id}:b 3 2 0 4"),
-            Command::Set60FPSLowPower(id) => format!("_setmode id:{id}:b 4 2 11 4"),
-            Command::Set70FPS(id) => format!("_setmode id:{id}:b 0 9 19 0"),
-            Command::Set144FPS(id) => format!("_setmode id:{id}:b 2 4 30 4"),
-            Command::PowerOffSensor(id) => format!("_sensoff id:{id}:b"),
-            Command::RestartSensor(id) => format!("_restart id:{id}:b"),
-            Command::StartMagneticCalibration(id) => format!("_start_mag_calib id:{id}:b"),
-            Command::StopMagneticCalibration(id) => format!("_stop_mag_calib id:{id}:b"),
-            Command::SetMagneticThreshold(id, min, max) => format!("_set_mag_th id:{id}:b {min} {max}"),
-            Command::SensorConfig(id) => format!("_sensconf id:{id}:b"),
-            Command::Config(id) => format!("_config"),
-            Command::InitializeCalibration(id) => format!("_initcalibration id:{id}"),
-            Command::Restart(id) => format!("_restart id:{id}"),
-            Command::SavePairing => String::from("_savepairing"),
+            Command::SetMagneticThreshold(target, _, _)
+            | Command::RequestSensorInfo(target)
+            | Command::EnableAhrs(target)
+            | Command::DisableAhrs(target)
+            | Command::SetMode(target, _)
+            | Command::PowerOffSensor(target)
+            | Command::RestartSensor(target)
+            | Command::StartMagneticCalibration(target)
+            | Command::StopMagneticCalibration(target)
+            | Command::SensorConfig(target)
+            | Command::Config(target)
+            | Command::InitializeCalibration(target) => Some(target),
+            Command::RestartAP
+            | Command::Alive
+            | Command::ListSensor
+            | Command::StartWifi
+            | Command::QuitConfig
+            | Command::AliveNoResponse
+            | Command::SavePairing => None,
+        }
+    }
+
+    /// Arguments that are not fixed by the protocol table.
+    pub(crate) fn args(&self) -> Vec<u8> {
+        match *self {
+            Command::SetMagneticThreshold(_, min, max) => vec![min, max],
+            Command::SetMode(_, mode) => mode.args().to_vec(),
+            _ => Vec::new(),
         }
     }
+
+    /// The same command addressed to `target`.
+    pub fn with_target(&self, target: SensorTarget) -> Command {
+        (self.spec().build)(target, &self.args())
+    }
+
+    /// One command per line sent: a `SensorTarget::Set` is expanded into one command per sensor.
+    pub fn expand(&self) -> Vec<Command> {
+        match self.target() {
+            Some(SensorTarget::Set(ids)) => ids.iter().map(|&id| self.with_target(SensorTarget::One(id))).collect(),
+            _ => vec![self.clone()],
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = ParseError;
+
+    /// Parse a command line as sent to the UniStation, the inverse of `as_str`.
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        protocol::decode(line)
+    }
+}
+
+/// Answer of the UniStation to a command, see `UnimotionManager::send_and_wait`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// The command is not answered.
+    None,
+    Ack(AcknowledgeType),
+    /// `_si` of the addressed sensors that answered, in the order they did.
+    SensorInfo(Vec<(u8, SensorInfo)>),
+    /// `_dev` of every slot, in slot order. Slots whose line got lost are left out.
+    SensorList(Vec<(u8, MacAddr6)>),
+    Restart { channel: u8, datamode: u8, auto_off: (u8, u64), devices: Vec<(u8, MacAddr6)> },
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -84,39 +633,346 @@ pub struct Channels {
     acknowledge_rx: crossbeam_channel::Receiver<AcknowledgeType>,
     datamode_rx: crossbeam_channel::Receiver<u8>,
     data_rx: crossbeam_channel::Receiver<Datagram>,
-    error_rx: crossbeam_channel::Receiver<()>,
+    error_rx: crossbeam_channel::Receiver<ParseError>,
+    fault_rx: crossbeam_channel::Receiver<UnimotionError>,
+    event_rx: crossbeam_channel::Receiver<Event>,
+}
+
+/// Builder for `UnimotionManager`.
+///
+/// Defaults to the values the UniStation ships with: `/dev/ttyUSB0` at 230400 baud.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct UnimotionManagerBuilder {
+    pub(crate) port_path: String,
+    pub(crate) serial_number: Option<UnimotionSerialNumber>,
+    pub(crate) baud_rate: u32,
+    pub(crate) read_timeout: Duration,
+    // Handshake timeouts
+    pub(crate) ack_timeout: Duration,
+    pub(crate) report_timeout: Duration,
+    pub(crate) sensor_timeout: Duration,
+    // Command scheduler
+    pub(crate) command_interval: Duration,
+    pub(crate) command_attempts: u32,
+    pub(crate) retry_delay: Duration,
+    pub(crate) reconnect_interval: Duration,
+    pub(crate) on_shutdown: ShutdownAction,
+}
+
+impl Default for UnimotionManagerBuilder {
+    fn default() -> Self {
+        UnimotionManagerBuilder {
+            port_path: String::from(DEFAULT_PORT_PATH),
+            serial_number: None,
+            baud_rate: DEFAULT_BAUD_RATE,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            report_timeout: DEFAULT_REPORT_TIMEOUT,
+            sensor_timeout: DEFAULT_SENSOR_TIMEOUT,
+            command_interval: DEFAULT_COMMAND_INTERVAL,
+            command_attempts: DEFAULT_COMMAND_ATTEMPTS,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            reconnect_interval: DEFAULT_RECONNECT_INTERVAL,
+            on_shutdown: ShutdownAction::Nothing,
+        }
+    }
+}
+
+impl UnimotionManagerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Path of the serial port the UniStation is connected to.
+    pub fn port_path(mut self, path: impl Into<String>) -> Self {
+        self.port_path = path.into();
+        self
+    }
+
+    /// Find the port by the USB serial number of the UniStation instead of `port_path`.
+    ///
+    /// The dongle keeps its serial number when it is plugged in again, not always its port.
+    pub fn serial_number(mut self, serial_number: UnimotionSerialNumber) -> Self {
+        self.serial_number = Some(serial_number);
+        self
+    }
+
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    /// Timeout of a single read on the serial port.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// How long the handshake waits for each `_ok` acknowledge.
+    pub fn ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// How long the handshake waits for each `_ch`, `_datamode`, `_auto_off` and `_dev` report.
+    pub fn report_timeout(mut self, timeout: Duration) -> Self {
+        self.report_timeout = timeout;
+        self
+    }
+
+    /// How long a sensor gets to answer `__sensinfo` before it is considered not responding.
+    pub fn sensor_timeout(mut self, timeout: Duration) -> Self {
+        self.sensor_timeout = timeout;
+        self
+    }
+
+    /// Minimum delay between two lines written to the UniStation.
+    pub fn command_interval(mut self, interval: Duration) -> Self {
+        self.command_interval = interval;
+        self
+    }
+
+    /// How many times `schedule` sends a command before giving up on its confirmation.
+    pub fn command_attempts(mut self, attempts: u32) -> Self {
+        self.command_attempts = attempts.max(1);
+        self
+    }
+
+    /// Delay before the first retry of an unconfirmed command, doubled after every attempt.
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Minimum delay between two attempts to reopen the port once the connection is lost.
+    pub fn reconnect_interval(mut self, interval: Duration) -> Self {
+        self.reconnect_interval = interval;
+        self
+    }
+
+    /// What the manager leaves the station doing when it is shut down or dropped.
+    pub fn on_shutdown(mut self, action: ShutdownAction) -> Self {
+        self.on_shutdown = action;
+        self
+    }
+
+    /// Use the port of a UniStation found by `discover_stations`.
+    pub fn station(mut self, station: &DiscoveredStation) -> Self {
+        self.serial_number = station.serial_number.clone();
+        self.port_path(station.port_path.clone())
+    }
+
+    /// Open the serial port and initialize the UniStation.
+    pub fn build(self) -> UnimotionResult<UnimotionManager> {
+        UnimotionManager::new(self)
+    }
+
+    fn scheduler_config(&self) -> SchedulerConfig {
+        SchedulerConfig {
+            command_interval: self.command_interval,
+            command_attempts: self.command_attempts,
+            retry_delay: self.retry_delay,
+            confirm_timeout: self.sensor_timeout,
+        }
+    }
+
+    /// Initialize the UniStation at the other end of `transport`.
+    ///
+    /// The port path, baud rate and read timeout are ignored, the transport is already open.
+    pub fn build_with_transport(self, transport: impl Transport + 'static) -> UnimotionResult<UnimotionManager> {
+        UnimotionManager::with_transport(self, Box::new(transport))
+    }
+
+    /// Initialize the UniStation at the other end of the transports opened by `connector`.
+    pub fn build_with_connector(self, connector: impl Connector + 'static) -> UnimotionResult<UnimotionManager> {
+        UnimotionManager::with_connector(self, Box::new(connector))
+    }
 }
 
 pub struct UnimotionManager {
     ingress_thread: Option<JoinHandle<()>>,
-    port: Box<dyn SerialPort>,
-    sensors: [UniSensorDevice; MAX_UNISENSOR_COUNT],
+    // Kept to start a new ingress thread after a reconnect
+    ingress: Ingress,
+    // Owns the writing half of the port
+    scheduler: Scheduler,
+    scheduler_config: SchedulerConfig,
+    // Reopens the port once the connection is lost, `None` for a transport given as is
+    connector: Option<Box<dyn Connector>>,
+    reconnect_interval: Duration,
+    last_reconnect: Option<Instant>,
+    on_shutdown: ShutdownAction,
+    // Shared with the ingress thread, which stores every `_si` it receives
+    sensors: Arc<Mutex<[UniSensorDevice; MAX_UNISENSOR_COUNT]>>,
+    // Modes set with `schedule`, restored after a reconnect if the same sensor is still in the slot
+    modes: [Option<(MacAddr6, SensorMode)>; MAX_UNISENSOR_COUNT],
     // Multi-producer multi-consumer channels for message passing.
     channels: Channels,
+    pub(crate) ack_timeout: Duration,
+    pub(crate) report_timeout: Duration,
+    pub(crate) sensor_timeout: Duration,
+    // Followed by the ingress thread from every response, driven by `begin`
+    pub(crate) station: Arc<SharedStation>,
+    // Unrelated responses met while waiting for a reply are forwarded to the event stream
+    pub(crate) event_tx: crossbeam_channel::Sender<Event>,
 }
 
-impl UnimotionManager {
-    /// Get `UnimotionManager` instance.
-    pub fn get_instance() -> Arc<Mutex<Self>> {
-        static mut SINGLETON: Option<Arc<Mutex<UnimotionManager>>> = None;
-        static ONCE: Once = Once::new();
+/// Serial port of the builder, looked up by USB serial number when one is set.
+struct SerialConnector {
+    port_path: String,
+    serial_number: Option<UnimotionSerialNumber>,
+    baud_rate: u32,
+    read_timeout: Duration,
+}
 
-        unsafe {
-            ONCE.call_once(|| {
-                let instance = UnimotionManager::new().unwrap();
+impl Connector for SerialConnector {
+    fn connect(&mut self) -> UnimotionResult<Box<dyn Transport>> {
+        let port_path = match &self.serial_number {
+            Some(serial_number) => discovery::find_station_port(serial_number)?,
+            None => self.port_path.clone(),
+        };
+        Ok(Box::new(SerialTransport::open(&port_path, self.baud_rate, self.read_timeout)?))
+    }
+}
 
-                SINGLETON = Some(instance);
-            });
+/// Everything the ingress thread dispatches the responses to.
+#[derive(Clone)]
+struct Ingress {
+    sensor_info_tx: crossbeam_channel::Sender<(u8, SensorInfo)>,
+    device_tx: crossbeam_channel::Sender<(u8, MacAddr6)>,
+    channel_tx: crossbeam_channel::Sender<u8>,
+    auto_off_tx: crossbeam_channel::Sender<(u8, u64)>,
+    acknowledge_tx: crossbeam_channel::Sender<AcknowledgeType>,
+    datamode_tx: crossbeam_channel::Sender<u8>,
+    data_tx: crossbeam_channel::Sender<Datagram>,
+    error_tx: crossbeam_channel::Sender<ParseError>,
+    fault_tx: crossbeam_channel::Sender<UnimotionError>,
+    event_tx: crossbeam_channel::Sender<Event>,
+    // Replaced on every reconnect, along with the scheduler reading it
+    confirm_tx: crossbeam_channel::Sender<(u8, SensorInfo)>,
+    sensors: Arc<Mutex<[UniSensorDevice; MAX_UNISENSOR_COUNT]>>,
+    station: Arc<SharedStation>,
+    // Set by `shutdown`, checked between two reads
+    stop: Arc<AtomicBool>,
+}
+
+impl Ingress {
+    fn spawn(self, reader: TransportReader) -> JoinHandle<()> {
+        std::thread::spawn(move || self.run(reader))
+    }
+
+    fn run(self, mut reader: TransportReader) {
+        let Ingress {
+            sensor_info_tx, device_tx, channel_tx, auto_off_tx, acknowledge_tx, datamode_tx,
+            data_tx, error_tx, fault_tx, event_tx, confirm_tx, sensors, station, stop,
+        } = self;
+        let mut decoder = ResponseDecoder::new();
+        let mut buffer = [0; 256];
+        'read: while !stop.load(Ordering::SeqCst) {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => {
+                    for res in decoder.push(&buffer[..n]) {
+                        // Ids are used as indices into the sensor table and the boot report
+                        let out_of_range = match &res {
+                            Ok(Response::SensorInfo(id, _)) | Ok(Response::Device(id, _)) => Some(*id),
+                            Ok(Response::Data(data)) => Some(data.id),
+                            _ => None,
+                        }.filter(|&id| id as usize >= MAX_UNISENSOR_COUNT);
+                        if let Some(id) = out_of_range {
+                            if fault_tx.send(UnimotionDeviceError::SensorIdOutOfRange(id).into()).is_err() { break 'read }
+                            continue;
+                        }
+                        if let Ok(response) = &res {
+                            if !matches!(response, Response::Data(_)) {
+                                let transition = station.update(|machine| machine.handle(response));
+                                // The station only restarts mid-session if someone else sent `_aprestart`, or on a brown-out
+                                if matches!(transition, Some((StationState::Streaming, StationState::Restarting))) {
+                                    let _ = event_tx.send(Event::StationReset);
+                                    let _ = fault_tx.send(UnimotionDeviceError::StationReset.into());
+                                }
+                            }
+                        }
+                        let sent = match res {
+                            Ok(Response::SensorInfo(id, info)) => { 
+                                lock_sensors(&sensors)[id as usize].sensor_info = Some(info);
+                                // The scheduler gets its own copy to confirm commands
+                                let _ = confirm_tx.send((id, info));
+                                sensor_info_tx.send((id, info)).is_ok()
+                            },
+                            Ok(Response::Device(id, addr)) => { 
+                                device_tx.send((id, addr)).is_ok()
+                            },
+                            Ok(Response::Channel(channel)) => { 
+                                channel_tx.send(channel).is_ok()
+                            },
+                            Ok(Response::AutoOff(enable, duration)) => { 
+                                auto_off_tx.send((enable, duration)).is_ok()
+                            },
+                            Ok(Response::Acknowledge(ack)) => { 
+                                acknowledge_tx.send(ack).is_ok()
+                            },
+                            Ok(Response::BootNoise(length)) => {
+                                event_tx.send(Event::StationBooted { length }).is_ok()
+                            },
+                            Ok(Response::Datamode(dm)) => { 
+                                datamode_tx.send(dm).is_ok()
+                            },
+                            Ok(Response::Data(data)) => { 
+                                data_tx.send(data).is_ok()
+                            },
+                            Err(e) => {
+                                // Nobody may be reading them, drop them rather than pile up
+                                !matches!(error_tx.try_send(e), Err(crossbeam_channel::TrySendError::Disconnected(_)))
+                            },
+                        };
 
-            match SINGLETON.clone() {
-                Some(manager) => manager,
-                None => unreachable!(),
+                        if !sent { break 'read }
+                    }
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (),
+                Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => (),
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    station.update(StationMachine::disconnect);
+                    let _ = fault_tx.send(UnimotionError::from(e));
+                    break;
+                }
             }
         }
+        // Disconnected before the fault is seen, so `update` knows to reconnect
+        station.update(StationMachine::disconnect);
+        let _ = fault_tx.send(UnimotionError::Disconnected);
     }
+}
 
+impl UnimotionManager {
     /// Constructor
-    fn new() -> UnimotionResult<Arc<Mutex<Self>>> {
+    ///
+    /// Every manager owns its port, ingress thread and channels, so one can be opened per UniStation.
+    pub fn new(builder: UnimotionManagerBuilder) -> UnimotionResult<Self> {
+        let connector = SerialConnector {
+            port_path: builder.port_path.clone(),
+            serial_number: builder.serial_number.clone(),
+            baud_rate: builder.baud_rate,
+            read_timeout: builder.read_timeout,
+        };
+        Self::with_connector(builder, Box::new(connector))
+    }
+
+    /// Construct a manager talking to the UniStation through `transport`.
+    ///
+    /// The manager cannot reconnect once `transport` is closed, see `with_connector`.
+    pub fn with_transport(builder: UnimotionManagerBuilder, transport: Box<dyn Transport>) -> UnimotionResult<Self> {
+        Self::connect(builder, transport, None)
+    }
+
+    /// Construct a manager opening its transport with `connector`, again whenever the connection is lost.
+    pub fn with_connector(builder: UnimotionManagerBuilder, mut connector: Box<dyn Connector>) -> UnimotionResult<Self> {
+        let transport = connector.connect()?;
+        Self::connect(builder, transport, Some(connector))
+    }
+
+    fn connect(builder: UnimotionManagerBuilder, transport: Box<dyn Transport>, connector: Option<Box<dyn Connector>>) -> UnimotionResult<Self> {
         let (sensor_info_tx, sensor_info_rx) = crossbeam_channel::unbounded();
         let (device_tx, device_rx) = crossbeam_channel::unbounded();
         let (channel_tx, channel_rx) = crossbeam_channel::unbounded();
@@ -124,184 +980,261 @@ impl UnimotionManager {
         let (acknowledge_tx, acknowledge_rx) = crossbeam_channel::unbounded();
         let (datamode_tx, datamode_rx) = crossbeam_channel::unbounded();
         let (data_tx, data_rx) = crossbeam_channel::unbounded();
-        let (error_tx, error_rx) = crossbeam_channel::unbounded();
-
-        let output = serialport::new("/dev/ttyUSB0", 230_400)
-            .timeout(Duration::from_millis(1000))
-            .open()?;
-        let input = output.try_clone()?;
-
-        let manager = {
-            let manager = UnimotionManager {
-                ingress_thread: None,
-                port: output,
-                sensors: [UniSensorDevice::empty(); MAX_UNISENSOR_COUNT],
-                // Consumer channels.
-                channels: Channels {
-                    sensor_info_rx,
-                    device_rx,
-                    channel_rx,
-                    auto_off_rx,
-                    acknowledge_rx,
-                    datamode_rx,
-                    data_rx,
-                    error_rx,
-                }
-            };
-            Arc::new(Mutex::new(manager))
-        };
+        let (error_tx, error_rx) = crossbeam_channel::bounded(PARSE_ERROR_CAPACITY);
+        let (fault_tx, fault_rx) = crossbeam_channel::unbounded();
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+        let (confirm_tx, confirm_rx) = crossbeam_channel::unbounded();
+        let station = Arc::new(SharedStation::new(event_tx.clone()));
+        let sensors = Arc::new(Mutex::new([UniSensorDevice::empty(); MAX_UNISENSOR_COUNT]));
 
-        let ingress_thread = {
-            let mut reader = std::io::BufReader::new(input);
-            
-            std::thread::spawn(move || {
-                let mut buffer = Vec::new();
-                loop {
-                    match reader.read_until(b'\n', &mut buffer) {
-                        Ok(n) => {
-                            // Print raw bytes
-                            println!("Read {} bytes: {:?}", buffer.len(), &buffer[0..n]);
-                            print!("ASCII: {}", String::from_utf8_lossy(&buffer[0..n]));
-                            
-                            let sent = match Response::from(buffer.clone()) {
-                                Response::SensorInfo(id, info) => { 
-                                    sensor_info_tx.send((id, info)).is_ok()
-                                },
-                                Response::Device(id, addr) => { 
-                                    device_tx.send((id, addr)).is_ok()
-                                },
-                                Response::Channel(channel) => { 
-                                    channel_tx.send(channel).is_ok()
-                                },
-                                Response::AutoOff(enable, duration) => { 
-                                    auto_off_tx.send((enable, duration)).is_ok()
-                                },
-                                Response::Acknowledge(ack) => { 
-                                    acknowledge_tx.send(ack).is_ok()
-                                },
-                                Response::Datamode(dm) => { 
-                                    datamode_tx.send(dm).is_ok()
-                                },
-                                Response::Data(data) => { 
-                                    data_tx.send(data).is_ok()
-                                },
-                                Response::Error => { 
-                                    error_tx.send(()).is_ok()
-                                },
-                            };
+        let (input, output) = transport.split()?;
+        let ingress = Ingress {
+            sensor_info_tx,
+            device_tx,
+            channel_tx,
+            auto_off_tx,
+            acknowledge_tx,
+            datamode_tx,
+            data_tx,
+            error_tx,
+            fault_tx,
+            event_tx: event_tx.clone(),
+            confirm_tx,
+            sensors: sensors.clone(),
+            station: station.clone(),
+            stop: Arc::new(AtomicBool::new(false)),
+        };
 
-                            if sent != true { return }
-                            buffer.clear();
-        
-                        }
-                        Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => (),
-                        Err(e) => {
-                            eprintln!("Error reading from serial port: {:?}", e);
-                            break; // Break the loop on error (you can handle it differently based on your requirements)
-                        }
-                    }
-                }
-            })
+        let mut manager = UnimotionManager {
+            ingress_thread: Some(ingress.clone().spawn(input)),
+            ingress,
+            scheduler: Scheduler::spawn(output, confirm_rx, builder.scheduler_config()),
+            scheduler_config: builder.scheduler_config(),
+            connector,
+            reconnect_interval: builder.reconnect_interval,
+            last_reconnect: None,
+            on_shutdown: builder.on_shutdown,
+            sensors,
+            modes: [None; MAX_UNISENSOR_COUNT],
+            // Consumer channels.
+            channels: Channels {
+                sensor_info_rx,
+                device_rx,
+                channel_rx,
+                auto_off_rx,
+                acknowledge_rx,
+                datamode_rx,
+                data_rx,
+                error_rx,
+                fault_rx,
+                event_rx,
+            },
+            ack_timeout: builder.ack_timeout,
+            report_timeout: builder.report_timeout,
+            sensor_timeout: builder.sensor_timeout,
+            station,
+            event_tx,
         };
 
-        {
-            let mut manager = match manager.lock() {
-                Ok(m) => m,
-                Err(m) => m.into_inner(),
-            };
-            manager.ingress_thread = Some(ingress_thread);
-            // UniStation initialization routine
-            manager.begin()?
-        }
+        // UniStation initialization routine
+        manager.begin()?;
         Ok(manager)
     }
 
     /// Initialize the UniStation
+    ///
+    /// The handshake follows `state()`: after a failure it resumes from the step that did not
+    /// complete, and a station that is already streaming is restarted.
     pub fn begin(&mut self) -> UnimotionResult<()> {
-        use PlaceholderError::*;
-        let output = &mut self.port;
-
-        println!("AP Restart");
-        writeln!(output, "{}", Command::RestartAP.as_str())?;
-        match Self::get_acknowledge_timeout(&mut  self.channels, Duration::from_millis(500)) {
-            Ok(AcknowledgeType::RestartAP) => (),
-            Ok(ack) => 
-                return Err(UnimotionError::from(UnexpectedAckError(AcknowledgeType::RestartAP, ack))),
-            Err(_) => 
-                return Err(UnimotionError::CrossbeamChannelError),
-        };
+        if self.station.state() == StationState::Streaming {
+            // Restarting on purpose is not a reset
+            self.station.update(StationMachine::restart);
+        }
 
-        match Self::get_channel_timeout(&mut  self.channels, Duration::from_millis(500)) {
-            Ok(ch) => (), Err(_) => return Err(UnimotionError::CrossbeamChannelError),
-        };
+        loop {
+            if self.station.step().next_command == Some(Command::RestartAP) {
+                self.station.update(StationMachine::restart);
+            }
+            let step = self.station.step();
+            let Some(waiting_for) = step.waiting_for else { break };
 
-        match Self::get_datamode_timeout(&mut  self.channels, Duration::from_millis(500)) {
-            Ok(dm) => (), Err(_) => return Err(UnimotionError::CrossbeamChannelError),
-        };
+            let timeout = match step.next_command {
+                Some(cmd) => {
+                    self.scheduler.write(cmd)?;
+                    self.ack_timeout
+                },
+                // The boot report comes on its own
+                None => self.report_timeout,
+            };
+            if !self.station.wait_step(step.step, timeout) {
+                self.station.update(StationMachine::fail);
+                return Err(UnimotionError::Timeout { waiting_for });
+            }
+        }
+        self.drain_handshake();
 
-        match Self::get_auto_off_timeout(&mut  self.channels, Duration::from_millis(500)) {
-            Ok((en, duration)) => (), Err(_) => return Err(UnimotionError::CrossbeamChannelError),
-        };
+        {
+            let mut sensors = self.sensor_table();
+            for (id, addr) in self.station.devices().into_iter().enumerate() {
+                sensors[id] = match addr {
+                    Some(addr) if !addr.is_nil() => UniSensorDevice { id: id as u8, mac_addr: addr, sensor_info: None },
+                    _ => UniSensorDevice::empty(),
+                };
+            }
+        }
 
-        match Self::get_devices_timeout(&mut  self.channels, Duration::from_millis(500)) {
-            Ok(devices) => {
-                for (id, addr) in devices {
-                    if addr.is_nil() == false {
-                        self.sensors[id as usize] = UniSensorDevice {
-                            id: id,
-                            mac_addr: addr,
-                            sensor_info: None
-                        }
-                    }
-                }
-            }, 
-            Err(_) => return Err(UnimotionError::CrossbeamChannelError),
-        };
+        // Sensors that are switched off stay without `sensor_info` until they answer
+        self.send_and_wait(Command::RequestSensorInfo(SensorTarget::All), self.sensor_timeout)?;
 
-        println!("Alive?");
-        writeln!(output, "{}", Command::Alive.as_str())?;
-        match Self::get_acknowledge_timeout(&mut self.channels, Duration::from_millis(500)) {
-            Ok(AcknowledgeType::Alive) => (),
-            Ok(ack) => 
-                return Err(UnimotionError::from(UnexpectedAckError(AcknowledgeType::Alive, ack))),
-            Err(_) => 
-                return Err(UnimotionError::CrossbeamChannelError),
-        };
+        Ok(())
+    }
 
-        println!("Start wifi");
-        writeln!(output, "{}", Command::StartWifi.as_str())?;
-        match Self::get_acknowledge_timeout(&mut self.channels, Duration::from_millis(500)) {
-            Ok(AcknowledgeType::StartWifi) => (),
-            Ok(ack) => 
-                return Err(UnimotionError::from(UnexpectedAckError(AcknowledgeType::StartWifi, ack))),
-            Err(_) => 
-                return Err(UnimotionError::CrossbeamChannelError),
-        };
+    /// Reopen the port with the connector and run the handshake again, if the connection was lost.
+    ///
+    /// Attempts are spaced by the builder's `reconnect_interval`, waiting for the next one if
+    /// needed. Once connected, the modes set before are restored on the sensors that report
+    /// another one; sensors that are off keep the mode they wake up with.
+    pub fn reconnect(&mut self) -> UnimotionResult<()> {
+        if self.state() != StationState::Disconnected {
+            return Ok(());
+        }
+        if self.connector.is_none() {
+            return Err(UnimotionError::Disconnected);
+        }
+        if let Some(last) = self.last_reconnect {
+            std::thread::sleep((last + self.reconnect_interval).saturating_duration_since(Instant::now()));
+        }
+        self.reconnect_now()
+    }
 
-        println!("Quit config");
-        writeln!(output, "{}", Command::QuitConfig.as_str())?;
-        match Self::get_acknowledge_timeout(&mut self.channels, Duration::from_millis(500)) {
-            Ok(AcknowledgeType::QuitConfig) => (),
-            Ok(ack) => 
-                return Err(UnimotionError::from(UnexpectedAckError(AcknowledgeType::QuitConfig, ack))),
-            Err(_) => 
-                return Err(UnimotionError::CrossbeamChannelError),
-        };
+    /// One attempt of `reconnect`, whether or not `reconnect_interval` has passed.
+    pub(crate) fn reconnect_now(&mut self) -> UnimotionResult<()> {
+        let Some(connector) = self.connector.as_mut() else { return Err(UnimotionError::Disconnected) };
+        self.last_reconnect = Some(Instant::now());
+
+        let (input, output) = connector.connect()?.split()?;
+        self.hang_up();
+        let (confirm_tx, confirm_rx) = crossbeam_channel::unbounded();
+        self.ingress.confirm_tx = confirm_tx;
+        self.scheduler = Scheduler::spawn(output, confirm_rx, self.scheduler_config);
+        self.ingress_thread = Some(self.ingress.clone().spawn(input));
 
+        if let Err(e) = self.begin() {
+            // Back to disconnected, so the next `update` tries again
+            self.hang_up();
+            return Err(e);
+        }
+        self.restore_modes();
         Ok(())
     }
 
-    // pub fn get_sensor_info(chls: &mut Channels) -> Result<(u8, SensorInfo), crossbeam_channel::RecvError> {
-    // }
+    /// Once the connection is lost, return its fault, then try to reconnect on the next calls.
+    fn check_connection(&mut self) -> UnimotionResult<()> {
+        if self.state() == StationState::Disconnected {
+            // The fault of the lost connection comes before the first attempt
+            if let Ok(fault) = self.channels.fault_rx.try_recv() {
+                return Err(fault);
+            }
+            self.reconnect()?;
+        }
+        Ok(())
+    }
 
-    // pub fn get_sensor_info_timeout(chls: &mut Channels, timeout: Duration) -> Result<(u8, SensorInfo), crossbeam_channel::RecvError> {
-    // }
+    /// Whether the station is connected or can still reconnect.
+    pub(crate) fn is_live(&self) -> bool {
+        self.state() != StationState::Disconnected || self.connector.is_some()
+    }
+
+    /// When `reconnect_now` is due, `None` unless disconnected with a connector. Like in `update`,
+    /// pending faults come before the attempt.
+    pub(crate) fn reconnect_at(&self) -> Option<Instant> {
+        if self.state() != StationState::Disconnected || self.connector.is_none() || !self.channels.fault_rx.is_empty() {
+            return None;
+        }
+        Some(self.last_reconnect.map_or_else(Instant::now, |last| last + self.reconnect_interval))
+    }
+
+    /// Report `fault` through `fault_receiver`, like the faults of the ingress thread.
+    pub(crate) fn push_fault(&self, fault: UnimotionError) {
+        let _ = self.ingress.fault_tx.send(fault);
+    }
+
+    /// Close the port and stop the ingress thread, which leaves the station disconnected.
+    fn hang_up(&mut self) {
+        self.scheduler.close();
+        self.ingress.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.ingress_thread.take() {
+            let _ = thread.join();
+        }
+        self.ingress.stop.store(false, Ordering::SeqCst);
+        // The lost connection is not news anymore
+        let faults: Vec<_> = self.channels.fault_rx.try_iter()
+            .filter(|fault| !matches!(fault, UnimotionError::Disconnected))
+            .collect();
+        for fault in faults {
+            let _ = self.ingress.fault_tx.send(fault);
+        }
+    }
+
+    /// Set the modes of `modes` again on the sensors that lost them.
+    fn restore_modes(&mut self) {
+        let lost: Vec<(u8, SensorMode)> = self.sensors().into_iter()
+            .filter_map(|sensor| {
+                let (addr, mode) = self.modes[sensor.id as usize]?;
+                let info = sensor.sensor_info?;
+                match addr == sensor.mac_addr && info.mode() != Some(mode) {
+                    true => Some((sensor.id, mode)),
+                    false => None,
+                }
+            })
+            .collect();
+        for (id, mode) in lost {
+            // A failed restore is reported like any fault, the connection itself is fine
+            if let Err(e) = self.set_mode(SensorTarget::One(id), mode) {
+                let _ = self.ingress.fault_tx.send(e);
+            }
+        }
+    }
+
+    /// Where the UniStation is in its lifecycle.
+    pub fn state(&self) -> StationState {
+        self.station.state()
+    }
+
+    /// Drop the lines `begin` went through, the state machine already took them into account.
+    fn drain_handshake(&self) {
+        let chls = &self.channels;
+        while chls.acknowledge_rx.try_recv().is_ok() {}
+        while chls.channel_rx.try_recv().is_ok() {}
+        while chls.datamode_rx.try_recv().is_ok() {}
+        while chls.auto_off_rx.try_recv().is_ok() {}
+        while chls.device_rx.try_recv().is_ok() {}
+    }
+
+    /// Wait for the next `_si` line, from sensor `id` or from any sensor if `None`.
+    ///
+    /// Lines from other sensors are forwarded to the event stream. The sensor table is updated
+    /// from every `_si` whether or not anyone waits for it.
+    pub fn get_sensor_info(&self, id: Option<u8>) -> UnimotionResult<(u8, SensorInfo)> {
+        loop {
+            let info = self.channels.sensor_info_rx.recv().map_err(|_| UnimotionError::ChannelClosed)?;
+            match sensor_info_from(id)(info) {
+                Ok(info) => break Ok(info),
+                Err(response) => { let _ = self.event_tx.send(Event::Unsolicited(response)); },
+            }
+        }
+    }
+
+    /// Like `get_sensor_info`, giving up after `timeout`.
+    pub fn get_sensor_info_timeout(&self, id: Option<u8>, timeout: Duration) -> UnimotionResult<(u8, SensorInfo)> {
+        self.wait_for(&self.channels.sensor_info_rx, Instant::now() + timeout, "_si", sensor_info_from(id))
+    }
 
     pub fn get_devices(chls: &mut Channels) -> Result<[(u8, MacAddr6); MAX_UNISENSOR_COUNT], crossbeam_channel::RecvError> {
         let mut counter = 0;
         let mut res = [(255, MacAddr6::nil()); MAX_UNISENSOR_COUNT];
         loop {
-            let _ = match chls.device_rx.recv() {
+            match chls.device_rx.recv() {
                 Ok((id, addr)) => res[id as usize] = (id, addr),
                 Err(e) => break Err(e),
             };
@@ -314,7 +1247,7 @@ impl UnimotionManager {
         let mut counter = 0;
         let mut res = [(255, MacAddr6::nil()); MAX_UNISENSOR_COUNT];
         loop {
-            let _ = match chls.device_rx.recv_timeout(timeout) {
+            match chls.device_rx.recv_timeout(timeout) {
                 Ok((id, addr)) => res[id as usize] = (id, addr),
                 Err(e) => break Err(e),
             };
@@ -364,52 +1297,370 @@ impl UnimotionManager {
     }
     
     pub fn flush(chls: &mut Channels) {
-        while let Ok(value) = chls.sensor_info_rx.try_recv() {
-            println!("Discarding {:?}", value);
+        while chls.sensor_info_rx.try_recv().is_ok() {}
+        while chls.device_rx.try_recv().is_ok() {}
+        while chls.channel_rx.try_recv().is_ok() {}
+        while chls.auto_off_rx.try_recv().is_ok() {}
+        while chls.acknowledge_rx.try_recv().is_ok() {}
+        while chls.datamode_rx.try_recv().is_ok() {}
+        while chls.data_rx.try_recv().is_ok() {}
+        while chls.error_rx.try_recv().is_ok() {}
+        while chls.fault_rx.try_recv().is_ok() {}
+        while chls.event_rx.try_recv().is_ok() {}
+    }
+
+    pub(crate) fn sensor_table(&self) -> MutexGuard<'_, [UniSensorDevice; MAX_UNISENSOR_COUNT]> {
+        lock_sensors(&self.sensors)
+    }
+
+    /// Check that `id` addresses a paired sensor.
+    fn check_sensor(&self, id: u8) -> Result<(), UnimotionDeviceError> {
+        match self.sensor_table().get(id as usize) {
+            None => Err(UnimotionDeviceError::SensorIdOutOfRange(id)),
+            Some(sensor) if sensor.mac_addr.is_nil() => Err(UnimotionDeviceError::SensorNotPaired(id)),
+            Some(_) => Ok(()),
         }
-        while let Ok(value) = chls.device_rx.try_recv() {
-            println!("Discarding {:?}", value);
+    }
+
+    /// Check that every sensor addressed by `cmd` is paired.
+    fn check_target(&self, cmd: &Command) -> Result<(), UnimotionDeviceError> {
+        match cmd.target() {
+            Some(SensorTarget::One(id)) => self.check_sensor(*id),
+            Some(SensorTarget::Set(ids)) => ids.iter().try_for_each(|&id| self.check_sensor(id)),
+            Some(SensorTarget::All) | None => Ok(()),
         }
-        while let Ok(value) = chls.channel_rx.try_recv() {
-            println!("Discarding {:?}", value);
+    }
+
+    /// Write `cmd` once, without waiting for any confirmation.
+    pub fn send_command(&mut self, cmd: Command) -> UnimotionResult<()> {
+        self.check_target(&cmd)?;
+        self.scheduler.write(cmd)
+    }
+
+    /// Queue `cmd` behind the commands already scheduled and return without waiting.
+    ///
+    /// Commands whose effect shows up in `_si` (`_setmode` presets, `_set_mag_th`) are sent to
+    /// every addressed sensor in turn, and sent again until a `__sensinfo` confirms it applied them.
+    /// A `SensorTarget::All` command is confirmed by the paired sensors.
+    pub fn schedule(&mut self, cmd: Command) -> UnimotionResult<CommandHandle> {
+        self.check_target(&cmd)?;
+        let confirm = Confirmation::of(&cmd).map(|confirmation| {
+            let ids = match cmd.target() {
+                Some(SensorTarget::One(id)) => vec![*id],
+                Some(SensorTarget::Set(ids)) => ids.clone(),
+                _ => self.sensors().iter().map(|sensor| sensor.id).collect(),
+            };
+            (ids, confirmation)
+        });
+        if let (Command::SetMode(_, mode), Some((ids, _))) = (&cmd, &confirm) {
+            let sensors = lock_sensors(&self.sensors);
+            for &id in ids {
+                self.modes[id as usize] = Some((sensors[id as usize].mac_addr, *mode));
+            }
         }
-        while let Ok(value) = chls.auto_off_rx.try_recv() {
-            println!("Discarding {:?}", value);
+        Ok(self.scheduler.submit(cmd, confirm))
+    }
+
+    /// Paired sensors, with the last `_si` each of them sent.
+    pub fn sensors(&self) -> Vec<UniSensorDevice> {
+        let mut v = Vec::new();
+        for sensor in *self.sensor_table() {
+            if !sensor.mac_addr.is_nil() {
+                v.push(sensor)
+            }
         }
-        while let Ok(value) = chls.acknowledge_rx.try_recv() {
-            println!("Discarding {:?}", value);
+        v
+    }
+
+    /// Ask sensor `id` for its `_si` report.
+    pub fn request_sensor_info(&mut self, id: u8) -> UnimotionResult<SensorInfo> {
+        match self.send_and_wait(Command::RequestSensorInfo(SensorTarget::One(id)), self.sensor_timeout)? {
+            Reply::SensorInfo(infos) => Ok(infos[0].1),
+            _ => unreachable!(),
         }
-        while let Ok(value) = chls.datamode_rx.try_recv() {
-            println!("Discarding {:?}", value);
+    }
+
+    /// Send `cmd` and wait up to `timeout` for the reply the protocol table expects.
+    ///
+    /// Replies already queued before `cmd` was sent, and replies of the right type from other
+    /// sensors, are unrelated: they are forwarded to `events` as `Event::Unsolicited`.
+    /// A `SensorTarget::All` request for `_si` returns the sensors that answered in time.
+    pub fn send_and_wait(&mut self, cmd: Command, timeout: Duration) -> UnimotionResult<Reply> {
+        self.check_target(&cmd)?;
+        self.exchange(cmd, timeout)
+    }
+
+    /// `send_and_wait` for sensors the manager does not know about yet.
+    pub(crate) fn exchange(&mut self, cmd: Command, timeout: Duration) -> UnimotionResult<Reply> {
+        let kind = cmd.reply_kind();
+        let mut wanted: Vec<u8> = match cmd.target() {
+            Some(SensorTarget::One(id)) => vec![*id],
+            Some(SensorTarget::Set(ids)) => ids.clone(),
+            Some(SensorTarget::All) => self.sensors().iter().map(|sensor| sensor.id).collect(),
+            None => Vec::new(),
+        };
+        // A sensor listed twice answers once per line, only the first one counts
+        wanted.sort_unstable();
+        wanted.dedup();
+        let broadcast = matches!(cmd.target(), Some(SensorTarget::All));
+
+        self.drain_stale(kind);
+        self.scheduler.write(cmd)?;
+        let deadline = Instant::now() + timeout;
+
+        match kind {
+            ReplyKind::Nothing => Ok(Reply::None),
+            ReplyKind::Ack(expected) => self.wait_ack(expected, deadline).map(Reply::Ack),
+            ReplyKind::SensorList => self.wait_devices(deadline).map(Reply::SensorList),
+            ReplyKind::SensorInfo => {
+                let mut infos: Vec<(u8, SensorInfo)> = Vec::new();
+                while infos.len() < wanted.len() {
+                    let res = self.wait_for(&self.channels.sensor_info_rx, deadline, "_si", |(id, info)| {
+                        match wanted.contains(&id) && !infos.iter().any(|(got, _)| *got == id) {
+                            true => Ok((id, info)),
+                            false => Err(Response::SensorInfo(id, info)),
+                        }
+                    });
+                    match res {
+                        Ok(info) => infos.push(info),
+                        // Powered off sensors do not answer a broadcast
+                        Err(UnimotionError::Timeout { .. }) if broadcast => break,
+                        Err(UnimotionError::Timeout { waiting_for }) => {
+                            return match wanted.iter().find(|id| !infos.iter().any(|(got, _)| got == *id)) {
+                                Some(&id) => Err(UnimotionDeviceError::SensorNotResponding(id).into()),
+                                None => Err(UnimotionError::Timeout { waiting_for }),
+                            };
+                        },
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(Reply::SensorInfo(infos))
+            },
+            ReplyKind::Restart => {
+                self.wait_ack(AcknowledgeType::RestartAP, deadline)?;
+                let channel = self.wait_for(&self.channels.channel_rx, deadline, "_ch", Ok)?;
+                let datamode = self.wait_for(&self.channels.datamode_rx, deadline, "_datamode", Ok)?;
+                let auto_off = self.wait_for(&self.channels.auto_off_rx, deadline, "_auto_off", Ok)?;
+                let devices = self.wait_devices(deadline)?;
+                Ok(Reply::Restart { channel, datamode, auto_off, devices })
+            },
+        }
+    }
+
+    /// Forward the replies of `kind` queued before a request was sent to the event stream.
+    fn drain_stale(&self, kind: ReplyKind) {
+        let chls = &self.channels;
+        let forward = |response| { let _ = self.event_tx.send(Event::Unsolicited(response)); };
+        if matches!(kind, ReplyKind::Ack(_) | ReplyKind::Restart) {
+            while let Ok(ack) = chls.acknowledge_rx.try_recv() {
+                forward(Response::Acknowledge(ack));
+            }
         }
-        while let Ok(value) = chls.data_rx.try_recv() {
-            println!("Discarding {:?}", value);
+        if matches!(kind, ReplyKind::SensorList | ReplyKind::Restart) {
+            while let Ok((id, addr)) = chls.device_rx.try_recv() {
+                forward(Response::Device(id, addr));
+            }
+        }
+        if kind == ReplyKind::SensorInfo {
+            while let Ok((id, info)) = chls.sensor_info_rx.try_recv() {
+                forward(Response::SensorInfo(id, info));
+            }
         }
-        while let Ok(value) = chls.error_rx.try_recv() {
-            println!("Discarding {:?}", value);
+        if kind == ReplyKind::Restart {
+            while let Ok(channel) = chls.channel_rx.try_recv() {
+                forward(Response::Channel(channel));
+            }
+            while let Ok(datamode) = chls.datamode_rx.try_recv() {
+                forward(Response::Datamode(datamode));
+            }
+            while let Ok((enable, duration)) = chls.auto_off_rx.try_recv() {
+                forward(Response::AutoOff(enable, duration));
+            }
         }
     }
 
-    pub fn send_command(&mut self, cmd: Command) -> UnimotionResult<()> {
-        let output = &mut self.port;
-        match writeln!(output, "{}", cmd.as_str()) {
-            Err(e) => Err(UnimotionError::from(e)),
-            Ok(()) => Ok(()),
+    /// Receive from `rx` until `accept` takes a value, the ones it gives back are forwarded to the event stream.
+    fn wait_for<T, R>(
+        &self,
+        rx: &crossbeam_channel::Receiver<T>,
+        deadline: Instant,
+        waiting_for: &'static str,
+        mut accept: impl FnMut(T) -> Result<R, Response>,
+    ) -> UnimotionResult<R> {
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(timeout) {
+                Ok(value) => match accept(value) {
+                    Ok(res) => break Ok(res),
+                    Err(response) => { let _ = self.event_tx.send(Event::Unsolicited(response)); },
+                },
+                Err(e) => break Err(UnimotionError::from_recv_timeout(e, waiting_for)),
+            }
         }
     }
 
-    pub fn sensors(&self) -> Vec<UniSensorDevice> {
-        let mut v = Vec::new();
-        for sensor in self.sensors {
-            if sensor.mac_addr.is_nil() == false {
-                v.push(sensor)
+    fn wait_ack(&self, expected: AcknowledgeType, deadline: Instant) -> UnimotionResult<AcknowledgeType> {
+        self.wait_for(&self.channels.acknowledge_rx, deadline, expected.as_str(), |ack| match ack == expected {
+            true => Ok(ack),
+            false => Err(Response::Acknowledge(ack)),
+        })
+    }
+
+    /// Collect one `_dev` line per slot, or the ones that arrived by `deadline`.
+    fn wait_devices(&self, deadline: Instant) -> UnimotionResult<Vec<(u8, MacAddr6)>> {
+        let mut devices: Vec<(u8, MacAddr6)> = Vec::new();
+        while devices.len() < MAX_UNISENSOR_COUNT {
+            let res = self.wait_for(&self.channels.device_rx, deadline, "_dev", |(id, addr)| {
+                match devices.iter().any(|(got, _)| *got == id) {
+                    true => Err(Response::Device(id, addr)),
+                    false => Ok((id, addr)),
+                }
+            });
+            match res {
+                Ok(device) => devices.push(device),
+                // A line lost on the way only leaves its slot out
+                Err(UnimotionError::Timeout { .. }) if !devices.is_empty() => break,
+                Err(e) => return Err(e),
             }
         }
-        return v
+        devices.sort_by_key(|(id, _)| *id);
+        Ok(devices)
     }
 
-    pub fn update(&mut self) -> (UniSensorDevice, Datagram) {
-        let data = Self::get_data(&mut self.channels).unwrap();
-        (self.sensors[data.id as usize], data)
+    /// Ask the station for its pairing table and apply the changes to `sensors`.
+    ///
+    /// Every change is sent to `events` and returned. Slots whose `_dev` line got lost are kept as they were.
+    pub fn refresh_sensor_list(&mut self) -> UnimotionResult<Vec<Event>> {
+        let devices = match self.send_and_wait(Command::ListSensor, self.report_timeout)? {
+            Reply::SensorList(devices) => devices,
+            _ => unreachable!(),
+        };
+
+        let mut changes = Vec::new();
+        let mut sensors = self.sensor_table();
+        for (id, addr) in devices {
+            let sensor = &mut sensors[id as usize];
+            let old = sensor.mac_addr;
+            let change = match (old.is_nil(), addr.is_nil()) {
+                _ if old == addr => continue,
+                (true, _) => Event::SensorAdded { id, addr },
+                (false, true) => Event::SensorRemoved { id, addr: old },
+                (false, false) => Event::SensorReplaced { id, old, new: addr },
+            };
+            *sensor = match addr.is_nil() {
+                true => UniSensorDevice::empty(),
+                false => UniSensorDevice { id, mac_addr: addr, sensor_info: None },
+            };
+            changes.push(change);
+        }
+        drop(sensors);
+
+        for change in &changes {
+            let _ = self.event_tx.send(change.clone());
+        }
+        Ok(changes)
+    }
+
+    /// Restart the station in config mode to pair a new sensor, see `PairingSession`.
+    pub fn start_pairing(&mut self) -> UnimotionResult<PairingSession<'_>> {
+        PairingSession::start(self)
+    }
+
+    /// Switch the sensors addressed by `target` to `mode` and check they did.
+    pub fn set_mode(&mut self, target: SensorTarget, mode: SensorMode) -> UnimotionResult<()> {
+        self.schedule(Command::SetMode(target, mode))?.wait()
+    }
+
+    /// Set the magnetometer thresholds of sensor `id` and check the sensor kept them.
+    ///
+    /// Older firmwares do not report the thresholds, the command is then only sent once.
+    pub fn set_magnetic_threshold(&mut self, id: u8, min: u8, max: u8) -> UnimotionResult<()> {
+        self.schedule(Command::SetMagneticThreshold(SensorTarget::One(id), min, max))?.wait()
+    }
+
+    /// Events reported by the station, to be polled or used in a `select!`.
+    pub fn events(&self) -> &crossbeam_channel::Receiver<Event> {
+        &self.channels.event_rx
+    }
+
+    /// Lines from the station that could not be parsed, the oldest `PARSE_ERROR_CAPACITY` ones
+    /// until read.
+    pub fn parse_errors(&self) -> &crossbeam_channel::Receiver<ParseError> {
+        &self.channels.error_rx
+    }
+
+    pub(crate) fn data_receiver(&self) -> &crossbeam_channel::Receiver<Datagram> {
+        &self.channels.data_rx
+    }
+
+    pub(crate) fn fault_receiver(&self) -> &crossbeam_channel::Receiver<UnimotionError> {
+        &self.channels.fault_rx
+    }
+
+    /// Pair a datagram with the sensor that sent it.
+    pub(crate) fn sensor_for(&self, data: &Datagram) -> UnimotionResult<UniSensorDevice> {
+        self.check_sensor(data.id)?;
+        Ok(self.sensor_table()[data.id as usize])
+    }
+
+    /// Block until a datagram or a fault is received.
+    ///
+    /// Once the connection is lost this returns `UnimotionError::Disconnected`, then every call
+    /// tries to reconnect, see `reconnect`.
+    pub fn update(&mut self) -> UnimotionResult<(UniSensorDevice, Datagram)> {
+        self.check_connection()?;
+        select! {
+            recv(self.channels.fault_rx) -> fault => Err(fault?),
+            recv(self.channels.data_rx) -> data => {
+                let data = data?;
+                Ok((self.sensor_for(&data)?, data))
+            },
+        }
+    }
+
+    /// Apply the builder's `on_shutdown` action, close the port and stop the ingress thread.
+    ///
+    /// Dropping the manager does the same, ignoring errors. Either way it can take up to the
+    /// read timeout for the ingress thread to notice.
+    pub fn shutdown(mut self) -> UnimotionResult<()> {
+        self.close()
+    }
+
+    fn close(&mut self) -> UnimotionResult<()> {
+        // Already closed by `shutdown`
+        let Some(thread) = self.ingress_thread.take() else { return Ok(()) };
+        let res = match (self.state(), self.on_shutdown) {
+            (StationState::Disconnected, _) | (_, ShutdownAction::Nothing) => Ok(()),
+            (_, ShutdownAction::PowerOffSensors) => self.scheduler.write(Command::PowerOffSensor(SensorTarget::All)),
+            (_, ShutdownAction::Restart) => {
+                self.station.update(StationMachine::restart);
+                self.scheduler.write(Command::RestartAP)
+            },
+        };
+
+        self.connector = None;
+        self.scheduler.close();
+        self.ingress.stop.store(true, Ordering::SeqCst);
+        let _ = thread.join();
+        res
+    }
+}
+
+impl Drop for UnimotionManager {
+    fn drop(&mut self) {
+        let _ = self.close();
+    }
+}
+
+/// The sensor table stays usable even if a thread panicked while holding it.
+fn lock_sensors(sensors: &Mutex<[UniSensorDevice; MAX_UNISENSOR_COUNT]>) -> MutexGuard<'_, [UniSensorDevice; MAX_UNISENSOR_COUNT]> {
+    sensors.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Accepts the `_si` of sensor `id`, or of any sensor if `None`, and gives the others back.
+fn sensor_info_from(id: Option<u8>) -> impl Fn((u8, SensorInfo)) -> Result<(u8, SensorInfo), Response> {
+    move |(sensor, info)| match id.is_none_or(|id| id == sensor) {
+        true => Ok((sensor, info)),
+        false => Err(Response::SensorInfo(sensor, info)),
     }
 }
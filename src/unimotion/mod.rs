@@ -1,10 +1,33 @@
 use crate::prelude::*;
 
 pub mod device;
+pub mod decoder;
+pub mod discovery;
+pub mod event;
+pub mod mode;
+pub mod pairing;
+pub mod protocol;
+pub mod state;
+pub mod transport;
+pub mod sim;
 // pub use manager::{UnimotionManager, UNIMOTION_RECEIVER};
-pub use manager::{UnimotionManager, Command};
+pub use manager::{UnimotionManager, UnimotionManagerBuilder, UnimotionSerialNumber, Command, SensorTarget, Reply, ShutdownAction};
+pub use decoder::ResponseDecoder;
+pub use event::Event;
+pub use mode::SensorMode;
+pub use pairing::{PairingSession, PairingCandidate};
+pub use scheduler::CommandHandle;
+pub use state::{StationState, StationMachine};
+pub use discovery::{discover_stations, find_station_port, DiscoveredStation};
+pub use station_set::{StationSet, StationId};
+pub use transport::{Transport, Connector, SerialTransport, TcpTransport, PipeTransport, pipe};
 
-use std::fmt::{Debug, Formatter};
-use std::sync::Arc;
+use std::fmt::Debug;
 
 mod manager;
+mod scheduler;
+mod station_set;
+#[cfg(test)]
+mod captures;
+#[cfg(test)]
+mod testing;
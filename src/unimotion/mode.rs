@@ -0,0 +1,139 @@
+//! Streaming modes of the sensors, set with `_setmode`.
+//!
+//! `_setmode` takes four numbers. The first is the datamode, which the station reports in
+//! `_datamode` and every sensor in byte 11 of its `_si`, and which selects the frame rate.
+//! The other three are relayed to the sensor as they are; what they control is unknown,
+//! so only the combinations captured from the official software are offered.
+use super::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presets() {
+        for mode in SensorMode::PRESETS {
+            assert_eq!(SensorMode::new(mode.rate(), mode.low_power()).unwrap(), mode);
+            assert_eq!(SensorMode::from_datamode(mode.datamode()), Some(mode));
+            assert_eq!(SensorMode::from_args(&mode.args()), Some(mode));
+        }
+        assert_eq!(SensorMode::from_datamode(1), None);
+        assert_eq!(SensorMode::from_args(&[2, 4, 30, 0]), None);
+    }
+
+    #[test]
+    fn test_unsupported_mode() {
+        assert!(matches!(
+            SensorMode::new(144, true),
+            Err(UnimotionDeviceError::UnsupportedSensorMode { rate: 144, low_power: true })
+        ));
+        assert!(matches!(
+            SensorMode::new(100, false),
+            Err(UnimotionDeviceError::UnsupportedSensorMode { rate: 100, low_power: false })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_deserialize_presets_only() {
+        for mode in SensorMode::PRESETS {
+            assert_eq!(SensorMode::try_from(ModeSetting::from(mode)).unwrap(), mode);
+        }
+        assert!(SensorMode::try_from(ModeSetting { rate: 144, low_power: true }).is_err());
+    }
+}
+
+/// Frame rate and power setting of a sensor.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(try_from = "ModeSetting", into = "ModeSetting"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SensorMode {
+    rate: u32,
+    low_power: bool,
+    datamode: u8,
+    // Remaining `_setmode` arguments
+    parameters: [u8; 3],
+}
+
+impl SensorMode {
+    pub const FPS_60: SensorMode = SensorMode::preset(60, false, [3, 2, 0, 4]);
+    pub const FPS_60_LOW_POWER: SensorMode = SensorMode::preset(60, true, [4, 2, 11, 4]);
+    pub const FPS_70: SensorMode = SensorMode::preset(70, false, [0, 9, 19, 0]);
+    pub const FPS_144: SensorMode = SensorMode::preset(144, false, [2, 4, 30, 4]);
+
+    /// Every mode the sensors are known to support.
+    pub const PRESETS: [SensorMode; 4] = [Self::FPS_60, Self::FPS_60_LOW_POWER, Self::FPS_70, Self::FPS_144];
+
+    const fn preset(rate: u32, low_power: bool, args: [u8; 4]) -> SensorMode {
+        SensorMode { rate, low_power, datamode: args[0], parameters: [args[1], args[2], args[3]] }
+    }
+
+    /// The supported mode streaming `rate` frames per second.
+    pub fn new(rate: u32, low_power: bool) -> Result<SensorMode, UnimotionDeviceError> {
+        Self::PRESETS.into_iter()
+            .find(|mode| mode.rate == rate && mode.low_power == low_power)
+            .ok_or(UnimotionDeviceError::UnsupportedSensorMode { rate, low_power })
+    }
+
+    /// Mode selected by `datamode`, as reported in `_si` and `_datamode`.
+    pub fn from_datamode(datamode: u8) -> Option<SensorMode> {
+        Self::PRESETS.into_iter().find(|mode| mode.datamode == datamode)
+    }
+
+    /// Mode set by the `_setmode` arguments `args`.
+    pub fn from_args(args: &[u8]) -> Option<SensorMode> {
+        Self::PRESETS.into_iter().find(|mode| mode.args() == args)
+    }
+
+    /// Frames per second streamed by the sensor.
+    pub fn rate(&self) -> u32 {
+        self.rate
+    }
+
+    pub fn low_power(&self) -> bool {
+        self.low_power
+    }
+
+    pub fn datamode(&self) -> u8 {
+        self.datamode
+    }
+
+    /// Arguments of `_setmode`.
+    pub fn args(&self) -> [u8; 4] {
+        let [a, b, c] = self.parameters;
+        [self.datamode, a, b, c]
+    }
+}
+
+/// Serialized form of a `SensorMode`, which only deserializes to one of the presets.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct ModeSetting {
+    rate: u32,
+    low_power: bool,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<ModeSetting> for SensorMode {
+    type Error = UnimotionDeviceError;
+
+    fn try_from(setting: ModeSetting) -> Result<Self, Self::Error> {
+        SensorMode::new(setting.rate, setting.low_power)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<SensorMode> for ModeSetting {
+    fn from(mode: SensorMode) -> Self {
+        ModeSetting { rate: mode.rate, low_power: mode.low_power }
+    }
+}
+
+impl std::fmt::Display for SensorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} fps", self.rate)?;
+        if self.low_power {
+            write!(f, ", low power")?;
+        }
+        Ok(())
+    }
+}
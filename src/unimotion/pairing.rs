@@ -0,0 +1,225 @@
+//! Guided pairing of a new UniSensor.
+//!
+//! The station only takes new sensors in config mode, i.e. between `_aprestart` and `_quitconfig`.
+//! A sensor switched on in pairing mode then shows up in the `_sensorlist` table. The table is
+//! kept in RAM until `_savepairing` writes it to flash.
+use super::*;
+use manager::{UnimotionManager, Command, SensorTarget, Reply, MAX_UNISENSOR_COUNT};
+use device::{UniSensorDevice, SensorInfo};
+use event::Event;
+use state::StationMachine;
+
+use macaddr::MacAddr6;
+
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sim::PAIRED_SENSORS;
+    use testing::{start, streaming_sensors, TIMEOUT};
+
+    use std::str::FromStr;
+
+    #[test]
+    fn test_pairing_accept() {
+        let (mut manager, sim) = start();
+        let addr = MacAddr6::from_str("E8:68:E7:53:56:01").unwrap();
+
+        let mut session = manager.start_pairing().unwrap().poll_interval(Duration::from_millis(20));
+        assert!(matches!(
+            session.next_sensor(Duration::from_millis(100)),
+            Err(UnimotionError::PairingError(PairingError::NoNewSensor))
+        ));
+        sim.pair(9, addr);
+        let candidate = session.next_sensor(TIMEOUT).unwrap();
+        assert_eq!((candidate.id, candidate.addr), (9, addr));
+        assert!(candidate.sensor_info.is_some());
+        assert_eq!(session.accept(candidate).unwrap().mac_addr, addr);
+
+        assert!(manager.events().try_iter().any(|event| event == Event::SensorAdded { id: 9, addr }));
+        assert_eq!(streaming_sensors(&manager, 100), [0, 1, 2, 3, 4, 5, 6, 7, 9]);
+        // Saved, so still there after a restart
+        manager.begin().unwrap();
+        assert_eq!(manager.sensors().len(), PAIRED_SENSORS.len() + 1);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_pairing_reject() {
+        let (mut manager, sim) = start();
+        let addr = MacAddr6::from_str("E8:68:E7:53:56:01").unwrap();
+
+        let mut session = manager.start_pairing().unwrap().poll_interval(Duration::from_millis(20));
+        sim.pair(9, addr);
+        let candidate = session.next_sensor(TIMEOUT).unwrap();
+        session.reject(&candidate).unwrap();
+        assert!(matches!(
+            session.next_sensor(Duration::from_millis(100)),
+            Err(UnimotionError::PairingError(PairingError::NoNewSensor))
+        ));
+        drop(session);
+
+        assert_eq!(manager.sensors().len(), PAIRED_SENSORS.len());
+        assert!(manager.update().is_ok());
+        manager.begin().unwrap();
+        assert_eq!(manager.sensors().len(), PAIRED_SENSORS.len());
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+}
+
+/// How often `next_sensor` asks the station for its table.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Sensor found in the station table that was not there when the session started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairingCandidate {
+    pub id: u8,
+    pub addr: MacAddr6,
+    /// `None` if the sensor did not answer `__sensinfo`.
+    pub sensor_info: Option<SensorInfo>,
+}
+
+/// Station held in config mode while a new sensor is paired, see `UnimotionManager::start_pairing`.
+///
+/// Dropping the session without accepting a sensor goes back to run mode without saving.
+/// The sensors that were streaming before keep their slots either way.
+pub struct PairingSession<'a> {
+    manager: &'a mut UnimotionManager,
+    // Addresses not to report, the table at the start plus the rejected sensors
+    known: [MacAddr6; MAX_UNISENSOR_COUNT],
+    poll_interval: Duration,
+    done: bool,
+}
+
+impl<'a> PairingSession<'a> {
+    pub(crate) fn start(manager: &'a mut UnimotionManager) -> UnimotionResult<Self> {
+        let mut known = [MacAddr6::nil(); MAX_UNISENSOR_COUNT];
+        for sensor in manager.sensors() {
+            known[sensor.id as usize] = sensor.mac_addr;
+        }
+        let mut session = PairingSession { manager, known, poll_interval: DEFAULT_POLL_INTERVAL, done: false };
+
+        session.enter_config().map_err(|e| PairingError::EnterConfig(Box::new(e)))?;
+        Ok(session)
+    }
+
+    fn enter_config(&mut self) -> UnimotionResult<()> {
+        let manager = &mut *self.manager;
+        // Restarting on purpose is not a reset
+        manager.station.update(StationMachine::restart);
+        let timeout = manager.ack_timeout + manager.report_timeout;
+        if let Reply::Restart { devices, .. } = manager.exchange(Command::RestartAP, timeout)? {
+            for (id, addr) in devices {
+                self.known[id as usize] = addr;
+            }
+        }
+        let ack_timeout = manager.ack_timeout;
+        manager.exchange(Command::StartWifi, ack_timeout)?;
+        Ok(())
+    }
+
+    /// How often `next_sensor` asks the station for its table.
+    pub fn poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Wait up to `timeout` for a sensor that is neither known nor rejected to show up.
+    pub fn next_sensor(&mut self, timeout: Duration) -> UnimotionResult<PairingCandidate> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let report_timeout = self.manager.report_timeout;
+            let devices = match self.manager.exchange(Command::ListSensor, report_timeout) {
+                Ok(Reply::SensorList(devices)) => devices,
+                Ok(_) => unreachable!(),
+                // The station is busy with the new sensor, ask again
+                Err(UnimotionError::Timeout { .. }) => Vec::new(),
+                Err(e) => return Err(e),
+            };
+
+            let new = devices.into_iter().find(|&(id, addr)| !addr.is_nil() && addr != self.known[id as usize]);
+            if let Some((id, addr)) = new {
+                let sensor_timeout = self.manager.sensor_timeout;
+                let sensor_info = match self.manager.exchange(Command::RequestSensorInfo(SensorTarget::One(id)), sensor_timeout) {
+                    Ok(Reply::SensorInfo(infos)) => infos.first().map(|(_, info)| *info),
+                    Ok(_) => unreachable!(),
+                    Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::SensorNotResponding(_))) => None,
+                    Err(e) => return Err(e),
+                };
+                return Ok(PairingCandidate { id, addr, sensor_info });
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(PairingError::NoNewSensor.into());
+            }
+            std::thread::sleep(self.poll_interval.min(deadline - now));
+        }
+    }
+
+    /// Switch `candidate` off and keep waiting for another sensor.
+    ///
+    /// The station still lists the sensor until it restarts, and saves it if another one is accepted.
+    pub fn reject(&mut self, candidate: &PairingCandidate) -> UnimotionResult<()> {
+        self.known[candidate.id as usize] = candidate.addr;
+        let timeout = self.manager.ack_timeout;
+        self.manager.exchange(Command::PowerOffSensor(SensorTarget::One(candidate.id)), timeout)?;
+        Ok(())
+    }
+
+    /// Save the table with `candidate` in it and go back to run mode.
+    pub fn accept(mut self, candidate: PairingCandidate) -> UnimotionResult<UniSensorDevice> {
+        self.save(&candidate)?;
+        self.quit_config()?;
+
+        let id = candidate.id;
+        let device = UniSensorDevice { id, mac_addr: candidate.addr, sensor_info: candidate.sensor_info };
+        let old = std::mem::replace(&mut self.manager.sensor_table()[id as usize], device).mac_addr;
+        let event = match old.is_nil() {
+            true => Event::SensorAdded { id, addr: candidate.addr },
+            false => Event::SensorReplaced { id, old, new: candidate.addr },
+        };
+        let _ = self.manager.event_tx.send(event);
+        Ok(device)
+    }
+
+    /// Go back to run mode without saving anything.
+    pub fn cancel(mut self) -> UnimotionResult<()> {
+        self.quit_config()
+    }
+
+    fn save(&mut self, candidate: &PairingCandidate) -> UnimotionResult<()> {
+        let report_timeout = self.manager.report_timeout;
+        let res = self.manager.exchange(Command::SavePairing, report_timeout)
+            // `_savepairing` is not answered, the table tells whether the sensor is still there
+            .and_then(|_| self.manager.exchange(Command::ListSensor, report_timeout));
+        match res {
+            Ok(Reply::SensorList(devices)) if devices.contains(&(candidate.id, candidate.addr)) => Ok(()),
+            Ok(_) => Err(PairingError::SensorLost(candidate.id).into()),
+            Err(e) => Err(PairingError::Save(Box::new(e)).into()),
+        }
+    }
+
+    fn quit_config(&mut self) -> UnimotionResult<()> {
+        self.done = true;
+        let ack_timeout = self.manager.ack_timeout;
+        // `_ok QUIT_CONFIG` moves the state machine back to streaming
+        match self.manager.exchange(Command::QuitConfig, ack_timeout) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(PairingError::QuitConfig(Box::new(e)).into()),
+        }
+    }
+}
+
+impl Drop for PairingSession<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = self.quit_config();
+        }
+    }
+}
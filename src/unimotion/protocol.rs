@@ -0,0 +1,285 @@
+//! The UniStation command set.
+//!
+//! `COMMANDS` is the single definition of every command: `Command::as_str`, `Command::from_str`
+//! and `doc/protocol.md` are all generated from it.
+use super::*;
+use manager::{Command, SensorTarget, BROADCAST_ID};
+use device::AcknowledgeType;
+use mode::SensorMode;
+
+use std::fmt::Write;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_encoded_bytes() {
+        let expected: [(Command, &[u8]); 22] = [
+            (Command::RestartAP, b"_aprestart\n"),
+            (Command::Alive, b"_alive\n"),
+            (Command::ListSensor, b"_sensorlist\n"),
+            (Command::StartWifi, b"_wifistart\n"),
+            (Command::QuitConfig, b"_quitconfig\n"),
+            (Command::RequestSensorInfo(SensorTarget::One(7)), b"__sensinfo id:7:b\n"),
+            (Command::AliveNoResponse, b"_alive_nores\n"),
+            (Command::EnableAhrs(SensorTarget::One(1)), b"_setahrsmode id:1:b 0\n"),
+            (Command::DisableAhrs(SensorTarget::One(1)), b"_setahrsmode id:1:b 1\n"),
+            (Command::SetMode(SensorTarget::One(2), SensorMode::FPS_60), b"_setmode id:2:b 3 2 0 4\n"),
+            (Command::SetMode(SensorTarget::One(2), SensorMode::FPS_60_LOW_POWER), b"_setmode id:2:b 4 2 11 4\n"),
+            (Command::SetMode(SensorTarget::One(2), SensorMode::FPS_70), b"_setmode id:2:b 0 9 19 0\n"),
+            (Command::SetMode(SensorTarget::One(2), SensorMode::FPS_144), b"_setmode id:2:b 2 4 30 4\n"),
+            (Command::PowerOffSensor(SensorTarget::One(23)), b"_sensoff id:23:b\n"),
+            (Command::RestartSensor(SensorTarget::One(4)), b"_restart id:4:b\n"),
+            (Command::StartMagneticCalibration(SensorTarget::One(5)), b"_start_mag_calib id:5:b\n"),
+            (Command::StopMagneticCalibration(SensorTarget::One(5)), b"_stop_mag_calib id:5:b\n"),
+            (Command::SetMagneticThreshold(SensorTarget::One(1), 0, 124), b"_set_mag_th id:1:b 0 124\n"),
+            (Command::SensorConfig(SensorTarget::One(6)), b"_sensconf id:6:b\n"),
+            (Command::Config(SensorTarget::One(6)), b"_config id:6:b\n"),
+            (Command::InitializeCalibration(SensorTarget::One(3)), b"_initcalibration id:3:b\n"),
+            (Command::SavePairing, b"_savepairing\n"),
+        ];
+        // `SetMode` covers every preset
+        assert_eq!(expected.len(), COMMANDS.len() + SensorMode::PRESETS.len() - 1);
+
+        for (cmd, bytes) in expected {
+            assert_eq!(cmd.encode(), bytes, "{:?}", cmd);
+            assert_eq!(Command::from_str(&cmd.as_str()), Ok(cmd));
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        // Every entry is reachable, i.e. no two entries encode the same command
+        for spec in COMMANDS {
+            let args: Vec<u8> = match spec.args {
+                Args::Bytes(names) => (0..names.len() as u8).collect(),
+                Args::Fixed(args) => args.to_vec(),
+                Args::Mode => SensorMode::FPS_144.args().to_vec(),
+                Args::None => Vec::new(),
+            };
+            let cmd = (spec.build)(SensorTarget::One(9), &args);
+            assert_eq!(cmd.spec(), spec, "{:?}", cmd);
+            assert_eq!(cmd.reply_kind(), spec.reply);
+        }
+    }
+
+    #[test]
+    fn test_protocol_doc() {
+        assert_eq!(include_str!("../../doc/protocol.md"), markdown_table());
+    }
+}
+
+/// Arguments following the command name (and the sensor target of addressed commands).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Args {
+    None,
+    /// Constant arguments, telling apart commands sharing a name.
+    Fixed(&'static [u8]),
+    /// Named byte arguments taken from the `Command`.
+    Bytes(&'static [&'static str]),
+    /// The arguments of one of `SensorMode::PRESETS`.
+    Mode,
+}
+
+/// What the UniStation answers to a command, see `manager::Reply` for the answer itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyKind {
+    /// Nothing, or at least nothing anyone has seen yet.
+    Nothing,
+    Ack(AcknowledgeType),
+    /// `_ok ESP_RESTART`, the boot noise, `_ch`, `_datamode`, `_auto_off` and the `_dev` list.
+    Restart,
+    /// One `_dev` line per slot.
+    SensorList,
+    /// One `_si` line per addressed sensor.
+    SensorInfo,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CommandSpec {
+    pub variant: &'static str,
+    pub name: &'static str,
+    /// Followed by an `id:<id>:b` target.
+    pub addressed: bool,
+    pub args: Args,
+    pub reply: ReplyKind,
+    /// Build the command from its target and arguments, the target is ignored by station commands.
+    pub build: fn(SensorTarget, &[u8]) -> Command,
+}
+
+impl PartialEq for CommandSpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.variant == other.variant
+    }
+}
+
+impl Eq for CommandSpec {}
+
+const fn station(variant: &'static str, name: &'static str, reply: ReplyKind, build: fn(SensorTarget, &[u8]) -> Command) -> CommandSpec {
+    CommandSpec { variant, name, addressed: false, args: Args::None, reply, build }
+}
+
+const fn sensor(variant: &'static str, name: &'static str, args: Args, reply: ReplyKind, build: fn(SensorTarget, &[u8]) -> Command) -> CommandSpec {
+    CommandSpec { variant, name, addressed: true, args, reply, build }
+}
+
+pub const COMMANDS: &[CommandSpec] = &[
+    station("RestartAP", "_aprestart", ReplyKind::Restart, |_, _| Command::RestartAP),
+    station("Alive", "_alive", ReplyKind::Ack(AcknowledgeType::Alive), |_, _| Command::Alive),
+    station("ListSensor", "_sensorlist", ReplyKind::SensorList, |_, _| Command::ListSensor),
+    station("StartWifi", "_wifistart", ReplyKind::Ack(AcknowledgeType::StartWifi), |_, _| Command::StartWifi),
+    station("QuitConfig", "_quitconfig", ReplyKind::Ack(AcknowledgeType::QuitConfig), |_, _| Command::QuitConfig),
+    sensor("RequestSensorInfo", "__sensinfo", Args::None, ReplyKind::SensorInfo, |target, _| Command::RequestSensorInfo(target)),
+    station("AliveNoResponse", "_alive_nores", ReplyKind::Nothing, |_, _| Command::AliveNoResponse),
+    sensor("EnableAhrs", "_setahrsmode", Args::Fixed(&[0]), ReplyKind::Nothing, |target, _| Command::EnableAhrs(target)),
+    sensor("DisableAhrs", "_setahrsmode", Args::Fixed(&[1]), ReplyKind::Nothing, |target, _| Command::DisableAhrs(target)),
+    // `Args::Mode` is checked first, so the arguments are one of the presets
+    sensor("SetMode", "_setmode", Args::Mode, ReplyKind::Nothing, |target, args| Command::SetMode(target, SensorMode::from_args(args).unwrap())),
+    sensor("PowerOffSensor", "_sensoff", Args::None, ReplyKind::Nothing, |target, _| Command::PowerOffSensor(target)),
+    sensor("RestartSensor", "_restart", Args::None, ReplyKind::Nothing, |target, _| Command::RestartSensor(target)),
+    sensor("StartMagneticCalibration", "_start_mag_calib", Args::None, ReplyKind::Nothing, |target, _| Command::StartMagneticCalibration(target)),
+    sensor("StopMagneticCalibration", "_stop_mag_calib", Args::None, ReplyKind::Nothing, |target, _| Command::StopMagneticCalibration(target)),
+    sensor("SetMagneticThreshold", "_set_mag_th", Args::Bytes(&["min", "max"]), ReplyKind::Nothing, |target, args| Command::SetMagneticThreshold(target, args[0], args[1])),
+    sensor("SensorConfig", "_sensconf", Args::None, ReplyKind::Nothing, |target, _| Command::SensorConfig(target)),
+    sensor("Config", "_config", Args::None, ReplyKind::Nothing, |target, _| Command::Config(target)),
+    sensor("InitializeCalibration", "_initcalibration", Args::None, ReplyKind::Nothing, |target, _| Command::InitializeCalibration(target)),
+    station("SavePairing", "_savepairing", ReplyKind::Nothing, |_, _| Command::SavePairing),
+];
+
+/// Entry of `COMMANDS` describing `cmd`.
+pub fn spec(cmd: &Command) -> &'static CommandSpec {
+    let target = cmd.target().cloned().unwrap_or(SensorTarget::All);
+    let args = cmd.args();
+    COMMANDS.iter()
+        // Check the arguments first, `build` indexes into them
+        .find(|spec| matches_args(spec.args, &args) && (spec.build)(target.clone(), &args) == *cmd)
+        .expect("every Command has an entry in COMMANDS")
+}
+
+fn matches_args(schema: Args, args: &[u8]) -> bool {
+    match schema {
+        Args::None => args.is_empty(),
+        Args::Fixed(_) => args.is_empty(),
+        Args::Bytes(names) => args.len() == names.len(),
+        Args::Mode => SensorMode::from_args(args).is_some(),
+    }
+}
+
+/// Line sent to the UniStation for `cmd`, without the line terminator.
+///
+/// `cmd` is a single line, i.e. not addressed to a `SensorTarget::Set`, see `Command::expand`.
+pub(crate) fn encode(cmd: &Command) -> String {
+    let spec = spec(cmd);
+    let args = cmd.args();
+
+    let mut line = String::from(spec.name);
+    match cmd.target() {
+        Some(SensorTarget::One(id)) => write!(line, " id:{}:b", id).unwrap(),
+        Some(SensorTarget::All) => write!(line, " id:{}:b", BROADCAST_ID).unwrap(),
+        Some(SensorTarget::Set(_)) => unreachable!("sets are expanded before encoding"),
+        None => (),
+    }
+    let args = match spec.args {
+        Args::Fixed(args) => args,
+        _ => &args,
+    };
+    for arg in args {
+        write!(line, " {}", arg).unwrap();
+    }
+    line
+}
+
+/// Parse a line sent to the UniStation, the inverse of `encode`.
+pub fn decode(line: &str) -> Result<Command, ParseError> {
+    let words: Vec<&str> = line.trim().split(' ').collect();
+    let name = words[0];
+    if name.is_empty() {
+        return Err(ParseError::EmptyLine);
+    }
+
+    let candidates: Vec<&CommandSpec> = COMMANDS.iter().filter(|spec| spec.name == name).collect();
+    // Commands sharing a name share their layout, only the fixed arguments differ
+    let layout = match candidates.first() {
+        Some(spec) => spec,
+        None => return Err(ParseError::UnknownPrefix(name.to_string())),
+    };
+    let arg_count = match layout.args {
+        Args::None => 0,
+        Args::Fixed(args) => args.len(),
+        Args::Bytes(names) => names.len(),
+        Args::Mode => SensorMode::FPS_60.args().len(),
+    };
+    let expected = 1 + layout.addressed as usize + arg_count;
+    if words.len() != expected {
+        return Err(ParseError::WrongFieldCount { expected, got: words.len() });
+    }
+
+    let (target, args) = match layout.addressed {
+        true => (parse_target(words[1])?, &words[2..]),
+        false => (SensorTarget::All, &words[1..]),
+    };
+    let args = args.iter()
+        .map(|word| word.parse().map_err(|_| ParseError::InvalidInteger(word.to_string())))
+        .collect::<Result<Vec<u8>, _>>()?;
+
+    let spec = candidates.into_iter().find(|spec| match spec.args {
+        Args::Fixed(fixed) => fixed == args.as_slice(),
+        Args::Mode => SensorMode::from_args(&args).is_some(),
+        _ => true,
+    });
+    match spec {
+        Some(spec) => Ok((spec.build)(target, &args)),
+        None => Err(ParseError::UnknownArguments(words[words.len() - arg_count..].join(" "))),
+    }
+}
+
+/// Parse the `id:<n>:b` target of a per-sensor command.
+fn parse_target(word: &str) -> Result<SensorTarget, ParseError> {
+    match word.strip_prefix("id:").and_then(|id| id.strip_suffix(":b")) {
+        Some(id) => id.parse::<u8>().map(SensorTarget::from).map_err(|_| ParseError::InvalidInteger(id.to_string())),
+        None => Err(ParseError::InvalidTarget(word.to_string())),
+    }
+}
+
+/// `COMMANDS` as the markdown table found in `doc/protocol.md`.
+pub fn markdown_table() -> String {
+    let mut doc = String::from("# UniStation commands\n\n");
+    doc.push_str("Generated from `unimotion::protocol::COMMANDS`. Every line is terminated by `\\n`.\n");
+    doc.push_str("Sensor commands are addressed to `id:<id>:b`, where `<id>` is 255 for every sensor.\n\n");
+    doc.push_str("| Command | Line | Reply |\n");
+    doc.push_str("|---|---|---|\n");
+    for spec in COMMANDS {
+        let mut line = String::from(spec.name);
+        if spec.addressed {
+            line.push_str(" id:<id>:b");
+        }
+        let mut rows = vec![(spec.variant.to_string(), line)];
+        match spec.args {
+            Args::None => (),
+            Args::Fixed(args) => args.iter().for_each(|arg| write!(rows[0].1, " {}", arg).unwrap()),
+            Args::Bytes(names) => names.iter().for_each(|name| write!(rows[0].1, " <{}>", name).unwrap()),
+            // One row per preset
+            Args::Mode => {
+                let (variant, line) = rows.remove(0);
+                for mode in SensorMode::PRESETS {
+                    let mut line = line.clone();
+                    mode.args().iter().for_each(|arg| write!(line, " {}", arg).unwrap());
+                    rows.push((format!("{}({})", variant, mode), line));
+                }
+            },
+        }
+        let reply = match spec.reply {
+            ReplyKind::Nothing => String::from("none"),
+            ReplyKind::Ack(ack) => format!("`{}`", ack),
+            ReplyKind::Restart => String::from("`_ok ESP_RESTART`, boot noise, `_ch`, `_datamode`, `_auto_off`, 24 × `_dev`"),
+            ReplyKind::SensorList => String::from("24 × `_dev`"),
+            ReplyKind::SensorInfo => String::from("`_si` per addressed sensor"),
+        };
+        for (variant, line) in rows {
+            writeln!(doc, "| `{}` | `{}` | {} |", variant, line, reply).unwrap();
+        }
+    }
+    doc
+}
@@ -0,0 +1,346 @@
+//! Serialized writes to the UniStation.
+//!
+//! The scheduler thread owns the writing half of the transport. Every line goes through its
+//! queue, so writes never interleave and are spaced by at least `command_interval`. Commands
+//! relayed over the air to a sensor can get lost: the ones whose effect shows up in `_si` are
+//! followed by a `__sensinfo` and sent again, with a doubling delay, until the sensor confirms.
+use super::*;
+use manager::{Command, SensorTarget};
+use device::SensorInfo;
+use transport::TransportWriter;
+
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{Receiver, Sender};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use manager::UnimotionManagerBuilder;
+    use mode::SensorMode;
+    use sim::Simulator;
+    use testing::{start, start_with, TIMEOUT};
+
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    type Written = (Instant, Vec<u8>);
+
+    /// Port keeping every write with the time it was made.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Written>>>);
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().push((Instant::now(), buf.to_vec()));
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_command_interval() {
+        let interval = Duration::from_millis(30);
+        let config = SchedulerConfig {
+            command_interval: interval,
+            command_attempts: 1,
+            retry_delay: interval,
+            confirm_timeout: interval,
+        };
+        let recorder = Recorder::default();
+        let (_confirm_tx, confirm_rx) = crossbeam_channel::unbounded();
+        let mut scheduler = Scheduler::spawn(Box::new(recorder.clone()), confirm_rx, config);
+        scheduler.write(Command::PowerOffSensor(SensorTarget::Set(vec![1, 2, 3]))).unwrap();
+        scheduler.write(Command::Alive).unwrap();
+        scheduler.close();
+
+        // The lines of a set are spaced like separate commands
+        let writes = recorder.0.lock().unwrap();
+        assert_eq!(writes.len(), 4);
+        assert_eq!(writes[1].1, b"_sensoff id:2:b\n");
+        for pair in writes.windows(2) {
+            assert!(pair[1].0.duration_since(pair[0].0) >= interval);
+        }
+    }
+
+    #[test]
+    fn test_lost_commands() {
+        let builder = UnimotionManagerBuilder::new().retry_delay(Duration::from_millis(10));
+        let (mut manager, sim) = start_with(Simulator::new().lose_commands(3), builder);
+        manager.schedule(Command::SetMode(SensorTarget::One(0), SensorMode::FPS_144)).unwrap().wait().unwrap();
+        assert_eq!(manager.request_sensor_info(0).unwrap().mode(), Some(SensorMode::FPS_144));
+
+        // Every sensor confirms a broadcast on its own
+        manager.set_mode(SensorTarget::All, SensorMode::FPS_70).unwrap();
+        for sensor in manager.sensors() {
+            assert_eq!(manager.request_sensor_info(sensor.id).unwrap().mode(), Some(SensorMode::FPS_70));
+        }
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_command_not_confirmed() {
+        let builder = UnimotionManagerBuilder::new()
+            .command_attempts(3)
+            .retry_delay(Duration::from_millis(10));
+        let (mut manager, sim) = start_with(Simulator::new().lose_commands(3), builder);
+
+        let handle = manager.schedule(Command::SetMode(SensorTarget::One(1), SensorMode::FPS_144)).unwrap();
+        assert!(matches!(
+            handle.wait_timeout(TIMEOUT),
+            Ok(Err(UnimotionError::UnimotionDeviceError(UnimotionDeviceError::CommandNotConfirmed(1))))
+        ));
+        assert_eq!(manager.request_sensor_info(1).unwrap().mode(), Some(SensorMode::FPS_60));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_magnetic_threshold() {
+        let (mut manager, sim) = start();
+        manager.set_magnetic_threshold(2, 10, 90).unwrap();
+        assert!(matches!(
+            manager.set_magnetic_threshold(2, 90, 10),
+            Err(UnimotionError::UnimotionDeviceError(
+                UnimotionDeviceError::MagneticCalibrationRejected { id: 2, min: 90, max: 10 }
+            ))
+        ));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+}
+
+/// What a sensor reports in `_si` once it applied a command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Confirmation {
+    Datamode(u8),
+    MagneticThresholds(u8, u8),
+}
+
+impl Confirmation {
+    /// Confirmation expected for `cmd`, `None` if `_si` does not reflect it.
+    pub(crate) fn of(cmd: &Command) -> Option<Confirmation> {
+        match cmd {
+            Command::SetMagneticThreshold(_, min, max) => Some(Confirmation::MagneticThresholds(*min, *max)),
+            Command::SetMode(_, mode) => Some(Confirmation::Datamode(mode.datamode())),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, info: &SensorInfo) -> bool {
+        match *self {
+            Confirmation::Datamode(datamode) => info.datamode() == datamode,
+            // Thresholds are not reported by every firmware
+            Confirmation::MagneticThresholds(min, max) => info.magnetic_thresholds().is_none_or(|th| th == (min, max)),
+        }
+    }
+
+    fn rejected(&self, id: u8) -> UnimotionDeviceError {
+        match *self {
+            Confirmation::MagneticThresholds(min, max) => UnimotionDeviceError::MagneticCalibrationRejected { id, min, max },
+            Confirmation::Datamode(_) => UnimotionDeviceError::CommandNotConfirmed(id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct SchedulerConfig {
+    /// Minimum delay between two writes.
+    pub(crate) command_interval: Duration,
+    /// Times a confirmed command is sent before giving up.
+    pub(crate) command_attempts: u32,
+    /// Delay before the first retry, doubled after every attempt.
+    pub(crate) retry_delay: Duration,
+    /// How long a sensor gets to answer the confirming `__sensinfo`.
+    pub(crate) confirm_timeout: Duration,
+}
+
+struct Job {
+    cmd: Command,
+    confirm: Option<(u8, Confirmation)>,
+    done: Sender<UnimotionResult<()>>,
+}
+
+/// Completion of commands queued with `UnimotionManager::schedule`.
+pub struct CommandHandle {
+    done: Receiver<UnimotionResult<()>>,
+    remaining: usize,
+    result: UnimotionResult<()>,
+}
+
+impl CommandHandle {
+    /// Block until every line of the command is written, and confirmed where possible.
+    ///
+    /// The first error is returned, the other lines are still sent.
+    pub fn wait(mut self) -> UnimotionResult<()> {
+        while self.remaining > 0 {
+            let res = self.done.recv().unwrap_or(Err(UnimotionError::ChannelClosed));
+            self.complete(res);
+        }
+        self.result
+    }
+
+    /// Same as `wait`, but give up after `timeout`, handing the handle back.
+    pub fn wait_timeout(mut self, timeout: Duration) -> Result<UnimotionResult<()>, Self> {
+        let deadline = Instant::now() + timeout;
+        while self.remaining > 0 {
+            match self.done.recv_deadline(deadline) {
+                Ok(res) => self.complete(res),
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => return Err(self),
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => self.complete(Err(UnimotionError::ChannelClosed)),
+            }
+        }
+        Ok(self.result)
+    }
+
+    /// Whether the command completed, without blocking. `wait` then returns immediately.
+    pub fn is_done(&mut self) -> bool {
+        while self.remaining > 0 {
+            match self.done.try_recv() {
+                Ok(res) => self.complete(res),
+                Err(crossbeam_channel::TryRecvError::Empty) => return false,
+                Err(crossbeam_channel::TryRecvError::Disconnected) => self.complete(Err(UnimotionError::ChannelClosed)),
+            }
+        }
+        true
+    }
+
+    fn complete(&mut self, res: UnimotionResult<()>) {
+        self.remaining -= 1;
+        if self.result.is_ok() {
+            self.result = res;
+        }
+    }
+}
+
+pub(crate) struct Scheduler {
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub(crate) fn spawn(port: TransportWriter, confirm_rx: Receiver<(u8, SensorInfo)>, config: SchedulerConfig) -> Self {
+        let (jobs, jobs_rx) = crossbeam_channel::unbounded();
+        let thread = std::thread::spawn(move || run(port, jobs_rx, confirm_rx, config));
+        Scheduler { jobs: Some(jobs), thread: Some(thread) }
+    }
+
+    /// Queue every line of `cmd`, confirming them if `confirm` is set.
+    ///
+    /// A confirmed command addressed to several sensors is queued once per sensor in `ids`.
+    pub(crate) fn submit(&self, cmd: Command, confirm: Option<(Vec<u8>, Confirmation)>) -> CommandHandle {
+        let (done, done_rx) = crossbeam_channel::unbounded();
+        let jobs = match confirm {
+            None => vec![Job { cmd, confirm: None, done: done.clone() }],
+            Some((ids, confirmation)) => ids.into_iter()
+                .map(|id| Job {
+                    cmd: cmd.with_target(SensorTarget::One(id)),
+                    confirm: Some((id, confirmation)),
+                    done: done.clone(),
+                })
+                .collect(),
+        };
+
+        let mut handle = CommandHandle { done: done_rx, remaining: jobs.len(), result: Ok(()) };
+        for job in jobs {
+            let sent = self.jobs.as_ref().map(|jobs| jobs.send(job));
+            if !matches!(sent, Some(Ok(()))) {
+                handle.complete(Err(UnimotionError::ChannelClosed));
+            }
+        }
+        handle
+    }
+
+    /// Write `cmd` once and wait until it is written.
+    pub(crate) fn write(&self, cmd: Command) -> UnimotionResult<()> {
+        self.submit(cmd, None).wait()
+    }
+
+    /// Write the queued jobs, then stop the thread and close the writing half of the port.
+    ///
+    /// Commands submitted afterwards fail with `UnimotionError::ChannelClosed`.
+    pub(crate) fn close(&mut self) {
+        // Closing the queue stops the thread once the queued jobs are done
+        self.jobs.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn run(mut port: TransportWriter, jobs: Receiver<Job>, confirm_rx: Receiver<(u8, SensorInfo)>, config: SchedulerConfig) {
+    let mut last_write: Option<Instant> = None;
+    let mut write = |cmd: &Command| -> UnimotionResult<()> {
+        // A command addressed to a set of sensors is one line per sensor
+        for line in cmd.encode().split_inclusive(|&b| b == b'\n') {
+            if let Some(last) = last_write {
+                std::thread::sleep((last + config.command_interval).saturating_duration_since(Instant::now()));
+            }
+            port.write_all(line)?;
+            port.flush()?;
+            last_write = Some(Instant::now());
+        }
+        Ok(())
+    };
+
+    for job in jobs.iter() {
+        let res = match job.confirm {
+            None => write(&job.cmd),
+            Some((id, confirmation)) => confirm(&mut write, &job.cmd, id, confirmation, &confirm_rx, &config),
+        };
+        let _ = job.done.send(res);
+    }
+}
+
+/// Send `cmd` until sensor `id` reports `confirmation` in its `_si`.
+fn confirm(
+    write: &mut impl FnMut(&Command) -> UnimotionResult<()>,
+    cmd: &Command,
+    id: u8,
+    confirmation: Confirmation,
+    confirm_rx: &Receiver<(u8, SensorInfo)>,
+    config: &SchedulerConfig,
+) -> UnimotionResult<()> {
+    let request = Command::RequestSensorInfo(SensorTarget::One(id));
+    let mut delay = config.retry_delay;
+    let mut error = UnimotionDeviceError::SensorNotResponding(id);
+
+    for attempt in 0..config.command_attempts {
+        if attempt > 0 {
+            std::thread::sleep(delay);
+            delay *= 2;
+        }
+        write(cmd)?;
+        // `_si` lines received before the request are not an answer to it
+        while confirm_rx.try_recv().is_ok() {}
+        write(&request)?;
+
+        let deadline = Instant::now() + config.confirm_timeout;
+        error = loop {
+            match confirm_rx.recv_deadline(deadline) {
+                Ok((sensor, info)) if sensor == id => match confirmation.matches(&info) {
+                    true => return Ok(()),
+                    false => break confirmation.rejected(id),
+                },
+                Ok(_) => (),
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => break UnimotionDeviceError::SensorNotResponding(id),
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return Err(UnimotionError::ChannelClosed),
+            }
+        };
+    }
+    Err(error.into())
+}
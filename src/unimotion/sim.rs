@@ -0,0 +1,436 @@
+//! A simulated UniStation.
+//!
+//! The simulator answers the handshake the same way the station captured in `doc/initialization` does,
+//! and streams synthetic datagrams for every paired sensor once the station leaves config mode.
+use super::*;
+use transport::Transport;
+
+use macaddr::MacAddr6;
+use base64::{Engine as _, engine::general_purpose};
+
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use manager::{MAX_UNISENSOR_COUNT, Command, SensorTarget};
+use mode::SensorMode;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use testing::start;
+
+    #[test]
+    fn test_handshake() {
+        let (manager, sim) = start();
+        let sensors = manager.sensors();
+        assert_eq!(sensors.len(), PAIRED_SENSORS.len());
+        assert_eq!(sensors[7].mac_addr, MacAddr6::from_str("AC:0B:FB:C5:4F:A5").unwrap());
+        assert!(sensors.iter().all(|sensor| sensor.sensor_info.is_some()));
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_data_stream() {
+        let (mut manager, sim) = start();
+        for _ in 0..32 {
+            let (device, data) = manager.update().unwrap();
+            assert_eq!(device.id, data.id);
+            assert!(!device.mac_addr.is_nil());
+            assert!(data.quaternions[0].is_some());
+        }
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+}
+
+/// Burst sent by the station right after `_ok ESP_RESTART` (without the trailing CR LF).
+pub const BOOT_NOISE: [u8; 519] = [
+    102, 6, 126, 30, 0, 24, 134, 102, 126, 248, 158, 0, 0, 24, 0, 24,
+    120, 0, 152, 224, 24, 30, 0, 0, 0, 24, 126, 24, 24, 120, 152, 152,
+    230, 152, 30, 230, 128, 152, 128, 230, 128, 152, 128, 24, 230, 248, 6, 126,
+    152, 0, 24, 230, 224, 0, 24, 134, 30, 102, 102, 254, 102, 128, 0, 102,
+    102, 230, 102, 152, 102, 96, 30, 102, 6, 6, 102, 134, 134, 224, 134, 24,
+    230, 158, 254, 158, 96, 134, 126, 0, 24, 102, 152, 120, 0, 24, 134, 30,
+    0, 102, 24, 230, 102, 96, 0, 230, 152, 6, 102, 254, 102, 96, 224, 152,
+    0, 230, 152, 6, 152, 254, 102, 128, 230, 152, 254, 152, 96, 102, 152, 152,
+    30, 152, 128, 30, 230, 158, 248, 158, 120, 102, 158, 126, 230, 248, 102, 158,
+    0, 134, 126, 0, 0, 24, 134, 0, 152, 152, 0, 102, 24, 230, 102, 0,
+    102, 126, 0, 24, 102, 120, 102, 102, 128, 152, 128, 30, 6, 158, 158, 158,
+    254, 102, 120, 24, 134, 126, 152, 0, 24, 0, 0, 24, 134, 6, 230, 102,
+    120, 24, 134, 126, 152, 0, 24, 0, 0, 24, 134, 96, 230, 102, 120, 24,
+    134, 126, 152, 0, 24, 0, 0, 24, 134, 30, 230, 248, 0, 152, 102, 120,
+    24, 134, 126, 152, 0, 24, 0, 0, 24, 134, 128, 158, 96, 230, 102, 120,
+    24, 134, 126, 152, 0, 24, 0, 0, 24, 134, 126, 0, 230, 102, 120, 24,
+    134, 126, 152, 0, 24, 0, 0, 152, 30, 152, 128, 230, 158, 254, 158, 96,
+    102, 120, 152, 24, 96, 134, 152, 254, 152, 224, 134, 0, 152, 224, 6, 158,
+    254, 158, 30, 230, 230, 0, 24, 120, 134, 158, 120, 134, 126, 6, 152, 30,
+    152, 128, 224, 158, 254, 158, 6, 6, 120, 152, 0, 24, 230, 120, 120, 134,
+    120, 120, 0, 0, 152, 224, 24, 24, 134, 224, 158, 102, 134, 158, 152, 24,
+    30, 230, 128, 152, 128, 224, 158, 254, 158, 6, 6, 120, 152, 0, 24, 230,
+    120, 120, 134, 120, 120, 0, 0, 152, 224, 152, 224, 6, 134, 224, 158, 102,
+    134, 158, 152, 152, 224, 24, 120, 6, 24, 30, 230, 128, 152, 128, 128, 158,
+    254, 158, 0, 0, 0, 24, 126, 6, 102, 158, 224, 158, 0, 152, 224, 24,
+    120, 0, 24, 248, 230, 158, 254, 158, 230, 158, 0, 24, 30, 230, 128, 152,
+    128, 224, 158, 254, 158, 6, 6, 120, 152, 0, 24, 6, 30, 0, 0, 152,
+    30, 128, 0, 0, 0, 24, 134, 224, 158, 102, 134, 158, 152, 152, 224, 0,
+    152, 248, 24, 30, 96, 152, 30, 152, 128, 224, 158, 254, 158, 6, 6, 120,
+    152, 0, 24, 6, 30, 0, 0, 24, 0, 24, 30, 0, 0, 24, 134, 224,
+    158, 102, 134, 158, 152, 24, 30, 30, 24, 24, 152, 30, 152, 128, 102, 134,
+    158, 96, 134, 248, 102, 0, 0, 24, 6, 30, 0, 0, 24, 0, 24, 30,
+    24, 6, 30, 230, 128, 152, 128,
+];
+
+/// `__sensinfo` payload of the sensors captured in `doc/_si`.
+pub const SENSOR_INFO: &str = "Zk4IOvJtHZgBCloDAgAEAAAAASgIAHw=";
+
+/// Sensors paired with the station captured in `doc/initialization`.
+pub const PAIRED_SENSORS: [&str; 8] = [
+    "E8:68:E7:53:56:99",
+    "E8:68:E7:53:56:DB",
+    "E8:68:E7:53:55:DE",
+    "E8:68:E7:53:56:DE",
+    "E8:68:E7:53:56:D4",
+    "E8:68:E7:53:55:72",
+    "BC:FF:4D:3F:CE:23",
+    "AC:0B:FB:C5:4F:A5",
+];
+
+/// How often the main loop checks for a stop request while idle.
+const IDLE_POLL: Duration = Duration::from_millis(50);
+
+/// State of the simulated UniStation.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    sensors: [MacAddr6; MAX_UNISENSOR_COUNT],
+    // Table in flash, restored on every restart
+    saved_sensors: [MacAddr6; MAX_UNISENSOR_COUNT],
+    datamodes: [u8; MAX_UNISENSOR_COUNT],
+    channel: u8,
+    datamode: u8,
+    auto_off: (u8, u64),
+    powered: [bool; MAX_UNISENSOR_COUNT],
+    magnetic_thresholds: [(u8, u8); MAX_UNISENSOR_COUNT],
+    sensor_info: Vec<u8>,
+    // Sensor commands still to drop
+    lost_commands: usize,
+    streaming: bool,
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        let mut sensors = [MacAddr6::nil(); MAX_UNISENSOR_COUNT];
+        for (id, addr) in PAIRED_SENSORS.iter().enumerate() {
+            sensors[id] = MacAddr6::from_str(addr).unwrap();
+        }
+        let datamode = SensorMode::FPS_60.datamode();
+        Simulator {
+            sensors,
+            saved_sensors: sensors,
+            datamodes: [datamode; MAX_UNISENSOR_COUNT],
+            channel: 1,
+            datamode,
+            auto_off: (1, 300_000),
+            powered: [true; MAX_UNISENSOR_COUNT],
+            magnetic_thresholds: [(0, 124); MAX_UNISENSOR_COUNT],
+            sensor_info: general_purpose::STANDARD.decode(SENSOR_INFO).unwrap(),
+            lost_commands: 0,
+            streaming: false,
+        }
+    }
+}
+
+/// Changes made to a running simulator through its handle.
+enum Control {
+    Pair(u8, MacAddr6),
+    Inject(Vec<u8>),
+}
+
+/// Running simulator, returned by `Simulator::spawn`.
+pub struct SimulatorHandle {
+    thread: JoinHandle<io::Result<()>>,
+    stop: Arc<AtomicBool>,
+    reboot: Arc<AtomicBool>,
+    control: crossbeam_channel::Sender<Control>,
+}
+
+impl SimulatorHandle {
+    /// Power cycle the station: it prints the boot noise and report and stops streaming, without any `_ok ESP_RESTART`.
+    pub fn reboot(&self) {
+        self.reboot.store(true, Ordering::Relaxed);
+    }
+
+    /// Pair (or unpair, with a nil address) the sensor in slot `id` of the running station.
+    ///
+    /// The change is lost on the next restart unless `_savepairing` is sent.
+    pub fn pair(&self, id: u8, addr: MacAddr6) {
+        let _ = self.control.send(Control::Pair(id, addr));
+    }
+
+    /// Send `bytes` to the host as they are, e.g. a line garbled on the wire.
+    pub fn inject(&self, bytes: &[u8]) {
+        let _ = self.control.send(Control::Inject(bytes.to_vec()));
+    }
+
+    /// Ask the simulator to stop and wait for it.
+    pub fn stop(self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join()
+    }
+
+    /// Wait until the other end closes the transport.
+    pub fn join(self) -> io::Result<()> {
+        match self.thread.join() {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::other("simulator thread panicked")),
+        }
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pair (or unpair, with a nil address) the sensor in slot `id`.
+    pub fn pair(mut self, id: u8, addr: MacAddr6) -> Self {
+        self.sensors[id as usize] = addr;
+        self.saved_sensors[id as usize] = addr;
+        self
+    }
+
+    /// Drop the next `n` commands relayed to the sensors, as a noisy radio link would.
+    ///
+    /// `__sensinfo` requests always go through.
+    pub fn lose_commands(mut self, n: usize) -> Self {
+        self.lost_commands = n;
+        self
+    }
+
+    /// Start the simulator on its own thread.
+    pub fn spawn(self, transport: Box<dyn Transport>) -> io::Result<SimulatorHandle> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let reboot = Arc::new(AtomicBool::new(false));
+        let (control, control_rx) = crossbeam_channel::unbounded();
+        let thread = {
+            let stop = stop.clone();
+            let reboot = reboot.clone();
+            std::thread::spawn(move || self.run_until(transport, &stop, &reboot, &control_rx))
+        };
+        Ok(SimulatorHandle { thread, stop, reboot, control })
+    }
+
+    /// Serve `transport` until the other end closes it.
+    pub fn run(self, transport: Box<dyn Transport>) -> io::Result<()> {
+        self.run_until(transport, &AtomicBool::new(false), &AtomicBool::new(false), &crossbeam_channel::never())
+    }
+
+    fn run_until(
+        self,
+        transport: Box<dyn Transport>,
+        stop: &AtomicBool,
+        reboot: &AtomicBool,
+        control: &crossbeam_channel::Receiver<Control>,
+    ) -> io::Result<()> {
+        match self.serve(transport, stop, reboot, control) {
+            // The host closed its end while a line was being sent
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            res => res,
+        }
+    }
+
+    fn serve(
+        mut self,
+        transport: Box<dyn Transport>,
+        stop: &AtomicBool,
+        reboot: &AtomicBool,
+        control: &crossbeam_channel::Receiver<Control>,
+    ) -> io::Result<()> {
+        let (input, mut output) = transport.split()?;
+        let (line_tx, line_rx) = crossbeam_channel::unbounded::<String>();
+
+        std::thread::spawn(move || {
+            let mut reader = io::BufReader::new(input);
+            let mut buffer = Vec::new();
+            loop {
+                match reader.read_until(b'\n', &mut buffer) {
+                    Ok(0) => break,
+                    Ok(_) => {
+                        if buffer.ends_with(b"\n") {
+                            let line = String::from_utf8_lossy(&buffer).trim().to_string();
+                            if line_tx.send(line).is_err() { break }
+                            buffer.clear();
+                        }
+                    },
+                    Err(ref e) if e.kind() == io::ErrorKind::TimedOut => (),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let start = Instant::now();
+        let mut next_due = [start; MAX_UNISENSOR_COUNT];
+        while !stop.load(Ordering::Relaxed) {
+            if reboot.swap(false, Ordering::Relaxed) {
+                self.boot(&mut output)?;
+            }
+            let now = Instant::now();
+            let wait = match self.next_datagram(&next_due) {
+                Some(id) => next_due[id].saturating_duration_since(now).min(IDLE_POLL),
+                None => IDLE_POLL,
+            };
+            let line = line_rx.recv_timeout(wait);
+            // Pairing changes made before the line was sent apply to it
+            for control in control.try_iter() {
+                match control {
+                    Control::Pair(id, addr) => self.sensors[id as usize] = addr,
+                    Control::Inject(bytes) => output.write_all(&bytes)?,
+                }
+            }
+            match line {
+                Ok(line) => {
+                    let was_streaming = self.streaming;
+                    self.handle(&line, &mut output)?;
+                    if self.streaming && !was_streaming {
+                        next_due = [Instant::now(); MAX_UNISENSOR_COUNT];
+                    }
+                },
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => (),
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+
+            let now = Instant::now();
+            while let Some(id) = self.next_datagram(&next_due) {
+                if next_due[id] > now { break }
+                let elapsed = now.duration_since(start);
+                writeln!(output, "{}\r", self.datagram(id as u8, elapsed))?;
+                next_due[id] += Duration::from_secs(1) / self.mode(id).rate();
+            }
+        }
+        Ok(())
+    }
+
+    /// Paired sensor whose next datagram is due first.
+    fn next_datagram(&self, next_due: &[Instant; MAX_UNISENSOR_COUNT]) -> Option<usize> {
+        if !self.streaming {
+            return None;
+        }
+        (0..MAX_UNISENSOR_COUNT)
+            .filter(|&id| !self.sensors[id].is_nil() && self.powered[id])
+            .min_by_key(|&id| next_due[id])
+    }
+
+    /// Answer a single command line.
+    fn handle(&mut self, line: &str, output: &mut impl Write) -> io::Result<()> {
+        // Lines that do not parse are ignored, like the station does.
+        let cmd = match Command::from_str(line) {
+            Ok(cmd) => cmd,
+            Err(_) => return Ok(()),
+        };
+
+        let relayed = cmd.target().is_some() && !matches!(cmd, Command::RequestSensorInfo(_));
+        if relayed && self.lost_commands > 0 {
+            self.lost_commands -= 1;
+            return Ok(());
+        }
+
+        match cmd {
+            Command::RestartAP => {
+                writeln!(output, "_ok ESP_RESTART\r")?;
+                self.boot(output)?;
+            },
+            Command::Alive => writeln!(output, "_ok\r")?,
+            Command::StartWifi => writeln!(output, "_ok WIFI_ON\r")?,
+            Command::QuitConfig => {
+                self.streaming = true;
+                writeln!(output, "_ok QUIT_CONFIG\r")?;
+            },
+            Command::ListSensor => self.write_sensor_list(output)?,
+            Command::SavePairing => self.saved_sensors = self.sensors,
+            Command::RequestSensorInfo(ref target) => {
+                for id in self.targets(target) {
+                    let mut info = self.sensor_info.clone();
+                    info[11] = self.datamodes[id];
+                    (info[21], info[22]) = self.magnetic_thresholds[id];
+                    writeln!(output, "_si {} {}\r", id, general_purpose::STANDARD.encode(info))?;
+                }
+            },
+            Command::PowerOffSensor(ref target) => {
+                for id in self.targets(target) {
+                    self.powered[id] = false;
+                }
+            },
+            // Thresholds the wrong way around are rejected by the sensor
+            Command::SetMagneticThreshold(ref target, min, max) if min <= max => {
+                for id in self.targets(target) {
+                    self.magnetic_thresholds[id] = (min, max);
+                }
+            },
+            Command::SetMode(ref target, mode) => {
+                for id in self.targets(target) {
+                    self.datamodes[id] = mode.datamode();
+                }
+            },
+            // Everything else is accepted silently, like the station does.
+            _ => (),
+        }
+        output.flush()
+    }
+
+    fn mode(&self, id: usize) -> SensorMode {
+        SensorMode::from_datamode(self.datamodes[id]).unwrap()
+    }
+
+    /// Start over from the saved table, printing the boot noise and report.
+    fn boot(&mut self, output: &mut impl Write) -> io::Result<()> {
+        self.streaming = false;
+        self.sensors = self.saved_sensors;
+        output.write_all(&BOOT_NOISE)?;
+        output.write_all(b"\r\n")?;
+        writeln!(output, "_ch {}\r", self.channel)?;
+        writeln!(output, "_datamode {}\r", self.datamode)?;
+        writeln!(output, "_auto_off {} {}\r", self.auto_off.0, self.auto_off.1)?;
+        self.write_sensor_list(output)
+    }
+
+    fn write_sensor_list(&self, output: &mut impl Write) -> io::Result<()> {
+        for (id, addr) in self.sensors.iter().enumerate() {
+            let bytes: Vec<String> = addr.as_bytes().iter().map(|b| format!("{:X}", b)).collect();
+            writeln!(output, "_dev {} {}\r", id, bytes.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Powered on, paired sensors addressed by `target` (255 addresses every sensor).
+    fn targets(&self, target: &SensorTarget) -> Vec<usize> {
+        (0..MAX_UNISENSOR_COUNT)
+            .filter(|&id| !self.sensors[id].is_nil() && self.powered[id])
+            .filter(|&id| target.contains(id as u8))
+            .collect()
+    }
+
+    /// Base64 IBQQAM datagram slowly spinning around the vertical axis.
+    fn datagram(&self, id: u8, elapsed: Duration) -> String {
+        let angle = elapsed.as_secs_f64() * 0.5 + id as f64;
+        let (sin, cos) = (angle / 2.0).sin_cos();
+        let scale = i16::MAX as f64;
+        // W Y Z X, Z is sent negated
+        let quaternion = [(cos * scale) as i16, (sin * scale) as i16, 0, 0];
+
+        let mut bytes = vec![id, 167];
+        for _ in 0..2 {
+            for value in quaternion {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes.extend_from_slice(&[0x80, 0xff]);
+        general_purpose::STANDARD_NO_PAD.encode(bytes)
+    }
+}
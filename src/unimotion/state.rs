@@ -0,0 +1,497 @@
+//! Lifecycle of the UniStation.
+//!
+//! After `_aprestart` (or a power cycle) the station prints its boot report: `_ch`, `_datamode`,
+//! `_auto_off` and one `_dev` per slot. It then stays in config mode until `_quitconfig`,
+//! which is acknowledged once it streams. `StationMachine` follows the station through these
+//! steps from the responses it sends, whoever sent the commands.
+use super::*;
+use manager::{Command, MAX_UNISENSOR_COUNT};
+use device::{Response, AcknowledgeType};
+use event::Event;
+
+use macaddr::MacAddr6;
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoder::ResponseDecoder;
+    use device::SensorInfo;
+    use crate::unimotion::captures::{captured_lines, INITIALIZATION};
+    use manager::UnimotionManager;
+    use sim::BOOT_NOISE;
+    use testing::{start, next_events, next_fault};
+
+    use std::str::FromStr;
+
+    fn feed(machine: &mut StationMachine, responses: &[Response]) -> Vec<Transition> {
+        responses.iter().filter_map(|response| machine.handle(response)).collect()
+    }
+
+    fn boot_report(devices: usize) -> Vec<Response> {
+        let mut responses = vec![
+            Response::Acknowledge(AcknowledgeType::RestartAP),
+            Response::BootNoise(519),
+            Response::Channel(1),
+            Response::Datamode(3),
+            Response::AutoOff(1, 300_000),
+        ];
+        responses.extend((0..devices as u8).map(|id| Response::Device(id, MacAddr6::nil())));
+        responses
+    }
+
+    fn ack(ack: AcknowledgeType) -> Response {
+        Response::Acknowledge(ack)
+    }
+
+    #[test]
+    fn test_captured_handshake() {
+        let mut decoder = ResponseDecoder::new();
+        let responses: Vec<Response> = decoder.push(&captured_lines(INITIALIZATION).concat()).map(Result::unwrap).collect();
+
+        let mut machine = StationMachine::new();
+        assert_eq!(machine.restart(), Some((StationState::Disconnected, StationState::Restarting)));
+        assert_eq!(feed(&mut machine, &responses), [
+            (StationState::Restarting, StationState::Configuring),
+            (StationState::Configuring, StationState::Streaming),
+        ]);
+        assert_eq!(machine.devices()[7], Some(MacAddr6::from_str("AC:0B:FB:C5:4F:A5").unwrap()));
+        assert_eq!(machine.next_command(), None);
+    }
+
+    #[test]
+    fn test_handshake_steps() {
+        let mut machine = StationMachine::new();
+        assert_eq!(machine.next_command(), Some(Command::RestartAP));
+        machine.restart();
+        assert_eq!(machine.waiting_for(), Some("_ok ESP_RESTART"));
+
+        let report = boot_report(MAX_UNISENSOR_COUNT);
+        assert!(feed(&mut machine, &report[..4]).is_empty());
+        assert_eq!(machine.next_command(), None);
+        assert_eq!(machine.waiting_for(), Some("_auto_off"));
+        assert_eq!(feed(&mut machine, &report[4..]), [(StationState::Restarting, StationState::Configuring)]);
+
+        for (expected, cmd) in [
+            (AcknowledgeType::Alive, Command::Alive),
+            (AcknowledgeType::StartWifi, Command::StartWifi),
+            (AcknowledgeType::QuitConfig, Command::QuitConfig),
+        ] {
+            assert_eq!(machine.next_command(), Some(cmd));
+            assert_eq!(machine.waiting_for(), Some(expected.as_str()));
+            machine.handle(&ack(expected));
+            // A late duplicate does not go back
+            assert_eq!(machine.handle(&ack(AcknowledgeType::Alive)), None);
+        }
+        assert_eq!(machine.state(), StationState::Streaming);
+        assert_eq!(machine.waiting_for(), None);
+    }
+
+    #[test]
+    fn test_reset_while_streaming() {
+        let mut machine = StationMachine::new();
+        machine.restart();
+        feed(&mut machine, &boot_report(MAX_UNISENSOR_COUNT));
+        machine.handle(&ack(AcknowledgeType::QuitConfig));
+        assert_eq!(machine.state(), StationState::Streaming);
+
+        // Power cycle: the boot noise comes without `_ok ESP_RESTART`
+        assert_eq!(machine.handle(&Response::BootNoise(519)), Some((StationState::Streaming, StationState::Restarting)));
+        assert_eq!(machine.next_command(), None);
+        let report = boot_report(MAX_UNISENSOR_COUNT);
+        assert_eq!(feed(&mut machine, &report[2..]), [(StationState::Restarting, StationState::Configuring)]);
+        assert_eq!(machine.next_command(), Some(Command::Alive));
+    }
+
+    #[test]
+    fn test_resume_after_error() {
+        // Lost `_dev` lines: the report never completes, the restart is sent again
+        let mut machine = StationMachine::new();
+        machine.restart();
+        feed(&mut machine, &boot_report(20));
+        assert_eq!(machine.waiting_for(), Some("_dev"));
+        assert_eq!(machine.fail(), Some((StationState::Restarting, StationState::Error)));
+        assert_eq!(machine.next_command(), Some(Command::RestartAP));
+
+        // Lost `_ok WIFI_ON`: the handshake resumes at `_wifistart`
+        machine.restart();
+        feed(&mut machine, &boot_report(MAX_UNISENSOR_COUNT));
+        machine.handle(&ack(AcknowledgeType::Alive));
+        assert_eq!(machine.fail(), Some((StationState::Configuring, StationState::Error)));
+        assert_eq!(machine.fail(), None);
+        assert_eq!(machine.next_command(), Some(Command::StartWifi));
+        assert_eq!(machine.handle(&ack(AcknowledgeType::StartWifi)), Some((StationState::Error, StationState::Configuring)));
+        assert_eq!(machine.handle(&ack(AcknowledgeType::QuitConfig)), Some((StationState::Configuring, StationState::Streaming)));
+    }
+
+    #[test]
+    fn test_skipped_acknowledge() {
+        let mut machine = StationMachine::new();
+        machine.restart();
+        feed(&mut machine, &boot_report(MAX_UNISENSOR_COUNT));
+        // `_ok` lost, `_ok WIFI_ON` acknowledges both steps
+        machine.handle(&ack(AcknowledgeType::StartWifi));
+        assert_eq!(machine.next_command(), Some(Command::QuitConfig));
+    }
+
+    #[test]
+    fn test_unrelated_lines() {
+        let mut machine = StationMachine::new();
+        machine.restart();
+        let mut report = boot_report(MAX_UNISENSOR_COUNT);
+        // A sensor answering a `__sensinfo` sent before the restart
+        report.insert(3, Response::SensorInfo(2, SensorInfo::from([0; 19])));
+        report.insert(6, ack(AcknowledgeType::Alive));
+        // Slots past the table are dropped
+        report.insert(7, Response::Device(30, MacAddr6::nil()));
+        assert_eq!(feed(&mut machine, &report), [(StationState::Restarting, StationState::Configuring)]);
+        assert_eq!(machine.next_command(), Some(Command::Alive));
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let mut machine = StationMachine::new();
+        assert_eq!(machine.disconnect(), None);
+        machine.restart();
+        assert_eq!(machine.disconnect(), Some((StationState::Restarting, StationState::Disconnected)));
+        assert_eq!(machine.next_command(), Some(Command::RestartAP));
+    }
+
+    fn expect_reset(manager: &mut UnimotionManager) {
+        let fault = next_fault(manager);
+        assert!(matches!(fault, UnimotionError::UnimotionDeviceError(UnimotionDeviceError::StationReset)));
+    }
+
+    #[test]
+    fn test_station_reset() {
+        let (mut manager, sim) = start();
+        // The handshake's own restart is not a reset
+        assert_eq!(manager.state(), StationState::Streaming);
+        assert_eq!(next_events(&manager, 4), [
+            Event::StateChanged { from: StationState::Disconnected, to: StationState::Restarting },
+            Event::StationBooted { length: BOOT_NOISE.len() },
+            Event::StateChanged { from: StationState::Restarting, to: StationState::Configuring },
+            Event::StateChanged { from: StationState::Configuring, to: StationState::Streaming },
+        ]);
+        assert!(manager.events().try_recv().is_err());
+
+        manager.send_command(Command::RestartAP).unwrap();
+        expect_reset(&mut manager);
+        assert_eq!(next_events(&manager, 2), [
+            Event::StateChanged { from: StationState::Streaming, to: StationState::Restarting },
+            Event::StationReset,
+        ]);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_station_reboot() {
+        let (mut manager, sim) = start();
+        assert!(manager.update().is_ok());
+        while manager.events().try_recv().is_ok() {}
+
+        sim.reboot();
+        expect_reset(&mut manager);
+        assert_eq!(next_events(&manager, 4), [
+            Event::StateChanged { from: StationState::Streaming, to: StationState::Restarting },
+            Event::StationReset,
+            Event::StationBooted { length: BOOT_NOISE.len() },
+            Event::StateChanged { from: StationState::Restarting, to: StationState::Configuring },
+        ]);
+
+        // The station stays in config mode until the handshake is run again
+        manager.begin().unwrap();
+        assert_eq!(manager.state(), StationState::Streaming);
+
+        drop(manager);
+        sim.join().unwrap();
+    }
+}
+
+/// Where the UniStation is in its lifecycle, see `UnimotionManager::state`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StationState {
+    /// Nothing heard from the station yet, or the connection was closed.
+    Disconnected,
+    /// Waiting for `_ok ESP_RESTART` and the boot report.
+    Restarting,
+    /// Boot report received, going through `_alive`, `_wifistart` and `_quitconfig`.
+    Configuring,
+    /// Out of config mode, sensors are streaming.
+    Streaming,
+    /// The handshake gave up, `UnimotionManager::begin` resumes it.
+    Error,
+}
+
+impl std::fmt::Display for StationState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(self, f)
+    }
+}
+
+/// State before and after a change.
+pub type Transition = (StationState, StationState);
+
+// Acknowledges of the config steps, in handshake order
+const CONFIG_STEPS: [AcknowledgeType; 3] = [AcknowledgeType::Alive, AcknowledgeType::StartWifi, AcknowledgeType::QuitConfig];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Progress {
+    Disconnected,
+    Restarting { booted: bool },
+    // Index in CONFIG_STEPS of the acknowledge waited for
+    Configuring(usize),
+    Streaming,
+}
+
+/// Values printed by the station when it boots.
+#[derive(Debug, Clone, Copy, Default)]
+struct BootReport {
+    channel: Option<u8>,
+    datamode: Option<u8>,
+    auto_off: Option<(u8, u64)>,
+    devices: [Option<MacAddr6>; MAX_UNISENSOR_COUNT],
+}
+
+impl BootReport {
+    fn missing(&self) -> Option<&'static str> {
+        if self.channel.is_none() {
+            Some("_ch")
+        } else if self.datamode.is_none() {
+            Some("_datamode")
+        } else if self.auto_off.is_none() {
+            Some("_auto_off")
+        } else if self.devices.iter().any(Option::is_none) {
+            Some("_dev")
+        } else {
+            None
+        }
+    }
+}
+
+/// Handshake state, driven by the responses of the station.
+///
+/// Every method returns the transition it caused, if any.
+#[derive(Debug, Clone)]
+pub struct StationMachine {
+    progress: Progress,
+    failed: bool,
+    report: BootReport,
+}
+
+impl Default for StationMachine {
+    fn default() -> Self {
+        StationMachine { progress: Progress::Disconnected, failed: false, report: BootReport::default() }
+    }
+}
+
+impl StationMachine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn state(&self) -> StationState {
+        match self.progress {
+            _ if self.failed => StationState::Error,
+            Progress::Disconnected => StationState::Disconnected,
+            Progress::Restarting { .. } => StationState::Restarting,
+            Progress::Configuring(_) => StationState::Configuring,
+            Progress::Streaming => StationState::Streaming,
+        }
+    }
+
+    /// Command moving the handshake forward, `None` while waiting for the boot report or once streaming.
+    pub fn next_command(&self) -> Option<Command> {
+        match self.progress {
+            Progress::Disconnected => Some(Command::RestartAP),
+            // A boot report that stopped halfway will not complete anymore
+            Progress::Restarting { booted } if !booted || self.failed => Some(Command::RestartAP),
+            Progress::Restarting { .. } => None,
+            Progress::Configuring(step) => Some(match CONFIG_STEPS[step] {
+                AcknowledgeType::Alive => Command::Alive,
+                AcknowledgeType::StartWifi => Command::StartWifi,
+                _ => Command::QuitConfig,
+            }),
+            Progress::Streaming => None,
+        }
+    }
+
+    /// Line the handshake is waiting for, `None` once streaming.
+    pub fn waiting_for(&self) -> Option<&'static str> {
+        match self.progress {
+            Progress::Disconnected | Progress::Restarting { booted: false } => Some(AcknowledgeType::RestartAP.as_str()),
+            Progress::Restarting { booted: true } => self.report.missing(),
+            Progress::Configuring(step) => Some(CONFIG_STEPS[step].as_str()),
+            Progress::Streaming => None,
+        }
+    }
+
+    /// `_dev` entries of the last boot report, `None` for the lines that did not arrive.
+    pub fn devices(&self) -> [Option<MacAddr6>; MAX_UNISENSOR_COUNT] {
+        self.report.devices
+    }
+
+    /// `_aprestart` is about to be sent.
+    pub fn restart(&mut self) -> Option<Transition> {
+        self.change(|machine| {
+            machine.progress = Progress::Restarting { booted: false };
+            machine.report = BootReport::default();
+        })
+    }
+
+    /// The station did not answer in time.
+    pub fn fail(&mut self) -> Option<Transition> {
+        self.change(|machine| machine.failed = true)
+    }
+
+    /// The connection to the station is closed.
+    pub fn disconnect(&mut self) -> Option<Transition> {
+        self.change(|machine| {
+            machine.progress = Progress::Disconnected;
+            machine.failed = false;
+        })
+    }
+
+    /// Follow a response of the station. Lines that do not belong to the current step are ignored.
+    pub fn handle(&mut self, response: &Response) -> Option<Transition> {
+        let before = self.state();
+        let progress = self.progress;
+        match (self.progress, response) {
+            // The station can restart at any time, e.g. on a brown-out
+            (_, Response::Acknowledge(AcknowledgeType::RestartAP)) | (_, Response::BootNoise(_)) => {
+                if self.progress != (Progress::Restarting { booted: true }) {
+                    self.report = BootReport::default();
+                }
+                self.progress = Progress::Restarting { booted: true };
+            },
+            (Progress::Restarting { booted: true }, report) => {
+                match *report {
+                    Response::Channel(channel) => self.report.channel = Some(channel),
+                    Response::Datamode(datamode) => self.report.datamode = Some(datamode),
+                    Response::AutoOff(enable, duration) => self.report.auto_off = Some((enable, duration)),
+                    Response::Device(id, addr) => {
+                        // Garbled ids are not worth failing the handshake for
+                        if let Some(slot) = self.report.devices.get_mut(id as usize) {
+                            *slot = Some(addr);
+                        }
+                    },
+                    _ => (),
+                }
+                if self.report.missing().is_none() {
+                    self.progress = Progress::Configuring(0);
+                }
+            },
+            (Progress::Configuring(step), Response::Acknowledge(ack)) => {
+                // A later step also acknowledges the skipped ones
+                if let Some(done) = CONFIG_STEPS.iter().position(|step| step == ack).filter(|&done| done >= step) {
+                    self.progress = match CONFIG_STEPS.get(done + 1) {
+                        Some(_) => Progress::Configuring(done + 1),
+                        None => Progress::Streaming,
+                    };
+                }
+            },
+            _ => (),
+        }
+
+        // Any progress ends a failed handshake
+        if self.progress != progress {
+            self.failed = false;
+        }
+        match before == self.state() {
+            true => None,
+            false => Some((before, self.state())),
+        }
+    }
+
+    fn change(&mut self, f: impl FnOnce(&mut Self)) -> Option<Transition> {
+        let before = self.state();
+        f(self);
+        match before == self.state() {
+            true => None,
+            false => Some((before, self.state())),
+        }
+    }
+
+}
+
+/// `StationMachine` shared by the manager and its ingress thread.
+///
+/// Every transition is sent to the event stream. `wait_step` wakes up whenever the line
+/// the handshake waits for changes.
+pub(crate) struct SharedStation {
+    inner: Mutex<Inner>,
+    changed: Condvar,
+    event_tx: crossbeam_channel::Sender<Event>,
+}
+
+struct Inner {
+    machine: StationMachine,
+    // Bumped whenever the handshake moves on
+    step: u64,
+}
+
+/// Where the handshake is, as seen by `SharedStation::step`.
+pub(crate) struct Step {
+    pub(crate) step: u64,
+    pub(crate) next_command: Option<Command>,
+    pub(crate) waiting_for: Option<&'static str>,
+}
+
+impl SharedStation {
+    pub(crate) fn new(event_tx: crossbeam_channel::Sender<Event>) -> Self {
+        let inner = Inner { machine: StationMachine::new(), step: 0 };
+        SharedStation { inner: Mutex::new(inner), changed: Condvar::new(), event_tx }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub(crate) fn state(&self) -> StationState {
+        self.lock().machine.state()
+    }
+
+    pub(crate) fn devices(&self) -> [Option<MacAddr6>; MAX_UNISENSOR_COUNT] {
+        self.lock().machine.devices()
+    }
+
+    pub(crate) fn step(&self) -> Step {
+        let inner = self.lock();
+        Step { step: inner.step, next_command: inner.machine.next_command(), waiting_for: inner.machine.waiting_for() }
+    }
+
+    /// Apply `f` to the machine and publish the transition it caused.
+    pub(crate) fn update(&self, f: impl FnOnce(&mut StationMachine) -> Option<Transition>) -> Option<Transition> {
+        let mut inner = self.lock();
+        let before = (inner.machine.progress, inner.machine.waiting_for());
+        let transition = f(&mut inner.machine);
+        if (inner.machine.progress, inner.machine.waiting_for()) != before {
+            inner.step += 1;
+            self.changed.notify_all();
+        }
+        // Sent under the lock, so the events come in the order of the transitions
+        if let Some((from, to)) = transition {
+            let _ = self.event_tx.send(Event::StateChanged { from, to });
+        }
+        transition
+    }
+
+    /// Wait up to `timeout` for the handshake to move past `step`, `false` if it did not.
+    pub(crate) fn wait_step(&self, step: u64, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut inner = self.lock();
+        while inner.step == step {
+            let now = Instant::now();
+            if now >= deadline {
+                return false;
+            }
+            inner = self.changed.wait_timeout(inner, deadline - now).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        true
+    }
+}
@@ -0,0 +1,308 @@
+use super::*;
+use manager::{UnimotionManager, UnimotionManagerBuilder};
+use device::{UniSensorDevice, Datagram};
+
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Select;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sim::{Simulator, SimulatorHandle};
+    use transport::{Transport, pipe};
+    use testing::{start, TIMEOUT};
+
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    /// Manager whose every connection is a new simulator pushed to `sims`.
+    fn reconnecting(sims: &Arc<Mutex<Vec<SimulatorHandle>>>) -> UnimotionManager {
+        let sims = sims.clone();
+        let connector = move || -> UnimotionResult<Box<dyn Transport>> {
+            let (local, remote) = pipe(Duration::from_millis(20));
+            sims.lock().unwrap().push(Simulator::new().spawn(Box::new(remote))?);
+            Ok(Box::new(local))
+        };
+        let builder = UnimotionManagerBuilder::new().reconnect_interval(Duration::from_millis(50));
+        builder.build_with_connector(connector).unwrap()
+    }
+
+    #[test]
+    fn test_failing_station() {
+        let (first, first_sim) = start();
+        let (second, second_sim) = start();
+        let mut set = StationSet::new();
+        let first_id = set.add(first);
+        let second_id = set.add(second);
+
+        // The error names the station that lost its connection
+        second_sim.stop().unwrap();
+        let start = Instant::now();
+        let e = loop {
+            assert!(start.elapsed() < TIMEOUT);
+            match set.update() {
+                Ok(_) => (),
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(e, UnimotionError::Station(id, ref e) if id == second_id && matches!(**e, UnimotionError::Disconnected)));
+        assert_eq!(e.to_string(), "station 1: the UniStation is disconnected");
+
+        // The other one keeps streaming, behind what the failed one received before
+        let mut streamed = 0;
+        while streamed < 32 {
+            if set.update().unwrap().0 == first_id { streamed += 1 }
+        }
+
+        drop(set);
+        first_sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_unplugged_station() {
+        let (healthy, healthy_sim) = start();
+        // Plugged in once, then gone for good
+        let sims = Arc::new(Mutex::new(Vec::new()));
+        let connector = {
+            let sims = sims.clone();
+            let mut connections = 0;
+            move || -> UnimotionResult<Box<dyn Transport>> {
+                connections += 1;
+                if connections > 1 {
+                    return Err(io::Error::other("unplugged").into());
+                }
+                let (local, remote) = pipe(Duration::from_millis(20));
+                sims.lock().unwrap().push(Simulator::new().spawn(Box::new(remote))?);
+                Ok(Box::new(local))
+            }
+        };
+        let builder = UnimotionManagerBuilder::new().reconnect_interval(Duration::from_millis(50));
+        let unplugged = builder.build_with_connector(connector).unwrap();
+
+        let mut set = StationSet::new();
+        let healthy_id = set.add(healthy);
+        let unplugged_id = set.add(unplugged);
+        sims.lock().unwrap().remove(0).stop().unwrap();
+
+        // Failed attempts are spaced by the interval and do not hold back the healthy station
+        let (mut streamed, mut failed) = (0, 0);
+        let start = Instant::now();
+        while failed < 3 {
+            assert!(start.elapsed() < TIMEOUT);
+            match set.update() {
+                Ok((id, _, _)) => if id == healthy_id { streamed += 1 },
+                Err(UnimotionError::Station(id, e)) => {
+                    assert_eq!(id, unplugged_id);
+                    if matches!(*e, UnimotionError::IOError(_)) { failed += 1 }
+                },
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        assert!(streamed > 10 * failed, "{} datagrams for {} failed attempts", streamed, failed);
+
+        drop(set);
+        healthy_sim.join().unwrap();
+    }
+
+    #[test]
+    fn test_all_disconnected() {
+        let (first, first_sim) = start();
+        let (second, second_sim) = start();
+        let mut set = StationSet::new();
+        set.add(first);
+        set.add(second);
+
+        first_sim.stop().unwrap();
+        second_sim.stop().unwrap();
+        // What was received before comes first, then the set gives up
+        let start = Instant::now();
+        while !matches!(set.update(), Err(UnimotionError::Disconnected)) {
+            assert!(start.elapsed() < TIMEOUT);
+        }
+        assert!(matches!(set.update(), Err(UnimotionError::Disconnected)));
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let sims = Arc::new(Mutex::new(Vec::new()));
+        let mut set = StationSet::new();
+        let id = set.add(reconnecting(&sims));
+
+        let unplugged = sims.lock().unwrap().remove(0);
+        unplugged.stop().unwrap();
+        let start = Instant::now();
+        loop {
+            assert!(start.elapsed() < TIMEOUT);
+            match set.update() {
+                Err(UnimotionError::Station(station, e)) => {
+                    assert_eq!(station, id);
+                    assert!(matches!(*e, UnimotionError::Disconnected));
+                    break;
+                },
+                res => assert!(res.is_ok()),
+            }
+        }
+
+        // The datagrams received before can still come first
+        while sims.lock().unwrap().is_empty() {
+            assert!(set.update().is_ok());
+        }
+        assert_eq!(set.update().unwrap().0, id);
+        assert_eq!(sims.lock().unwrap().len(), 1);
+
+        drop(set);
+        for sim in sims.lock().unwrap().drain(..) {
+            sim.join().unwrap();
+        }
+    }
+}
+
+/// Index of a UniStation inside a `StationSet`.
+///
+/// Sensor ids are only unique per station, so a sensor is identified by `(StationId, u8)`.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd)]
+pub struct StationId(pub usize);
+
+/// Several UniStations whose datagrams are merged into a single stream.
+#[derive(Default)]
+pub struct StationSet {
+    stations: Vec<UnimotionManager>,
+}
+
+impl StationSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open and initialize one `UnimotionManager` per builder.
+    pub fn open(builders: impl IntoIterator<Item = UnimotionManagerBuilder>) -> UnimotionResult<Self> {
+        let mut set = Self::new();
+        for builder in builders {
+            let manager = builder.build().map_err(|e| station_error(StationId(set.len()), e))?;
+            set.add(manager);
+        }
+        Ok(set)
+    }
+
+    pub fn add(&mut self, manager: UnimotionManager) -> StationId {
+        self.stations.push(manager);
+        StationId(self.stations.len() - 1)
+    }
+
+    pub fn station(&self, id: StationId) -> Option<&UnimotionManager> {
+        self.stations.get(id.0)
+    }
+
+    pub fn station_mut(&mut self, id: StationId) -> Option<&mut UnimotionManager> {
+        self.stations.get_mut(id.0)
+    }
+
+    pub fn stations(&self) -> impl Iterator<Item = (StationId, &UnimotionManager)> {
+        self.stations.iter().enumerate().map(|(i, m)| (StationId(i), m))
+    }
+
+    pub fn len(&self) -> usize {
+        self.stations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stations.is_empty()
+    }
+
+    /// Paired sensors of every station, tagged with their station.
+    pub fn sensors(&self) -> Vec<(StationId, UniSensorDevice)> {
+        self.stations()
+            .flat_map(|(id, m)| m.sensors().into_iter().map(move |s| (id, s)))
+            .collect()
+    }
+
+    /// Block until any station receives a datagram.
+    ///
+    /// Stations that lost their connection are reconnected every `reconnect_interval` meanwhile,
+    /// a failed attempt is returned like any other error of the station, as `UnimotionError::Station`.
+    /// Once no station is connected or can reconnect, `UnimotionError::Disconnected` is returned.
+    pub fn update(&mut self) -> UnimotionResult<(StationId, UniSensorDevice, Datagram)> {
+        match self.select(None) {
+            Some(res) => res,
+            None => unreachable!(),
+        }
+    }
+
+    /// Wait up to `timeout` for any station to receive a datagram.
+    pub fn update_timeout(&mut self, timeout: Duration) -> Option<UnimotionResult<(StationId, UniSensorDevice, Datagram)>> {
+        self.select(Some(timeout))
+    }
+
+    fn select(&mut self, timeout: Option<Duration>) -> Option<UnimotionResult<(StationId, UniSensorDevice, Datagram)>> {
+        if self.stations.is_empty() {
+            return match timeout {
+                Some(timeout) => { std::thread::sleep(timeout); None },
+                None => Some(Err(UnimotionError::Disconnected)),
+            };
+        }
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let next_reconnect = self.reconnect_due();
+            let live = self.stations.iter().any(UnimotionManager::is_live);
+
+            // Data receivers come first, then the fault receivers in the same order
+            let mut sel = Select::new();
+            for station in &self.stations {
+                sel.recv(station.data_receiver());
+            }
+            for station in &self.stations {
+                sel.recv(station.fault_receiver());
+            }
+            let oper = match (live, deadline.into_iter().chain(next_reconnect).min()) {
+                // What the dead stations received before is still delivered
+                (false, _) => match sel.try_select() {
+                    Ok(oper) => oper,
+                    Err(_) => return Some(Err(UnimotionError::Disconnected)),
+                },
+                (true, Some(wake)) => match sel.select_deadline(wake) {
+                    Ok(oper) => oper,
+                    Err(_) if deadline.is_some_and(|deadline| Instant::now() >= deadline) => return None,
+                    // Time for a reconnection attempt
+                    Err(_) => continue,
+                },
+                (true, None) => sel.select(),
+            };
+
+            let index = oper.index();
+            if index >= self.stations.len() {
+                let id = StationId(index - self.stations.len());
+                return match oper.recv(self.stations[id.0].fault_receiver()) {
+                    Ok(fault) => Some(Err(station_error(id, fault))),
+                    Err(_) => Some(Err(station_error(id, UnimotionError::Disconnected))),
+                };
+            }
+            let id = StationId(index);
+            let station = &self.stations[index];
+            let res = match oper.recv(station.data_receiver()) {
+                Ok(data) => station.sensor_for(&data).map(|sensor| (id, sensor, data)),
+                // The station's ingress thread stopped
+                Err(_) => Err(UnimotionError::Disconnected),
+            };
+            return Some(res.map_err(|e| station_error(id, e)));
+        }
+    }
+
+    /// Try to reconnect the stations whose `reconnect_interval` has passed, and return when the
+    /// next attempt is due. A failed attempt is a fault of its station.
+    fn reconnect_due(&mut self) -> Option<Instant> {
+        for station in &mut self.stations {
+            if station.reconnect_at().is_some_and(|at| at <= Instant::now()) {
+                if let Err(e) = station.reconnect_now() {
+                    station.push_fault(e);
+                }
+            }
+        }
+        self.stations.iter().filter_map(UnimotionManager::reconnect_at).min()
+    }
+}
+
+fn station_error(id: StationId, e: UnimotionError) -> UnimotionError {
+    UnimotionError::Station(id, Box::new(e))
+}
@@ -0,0 +1,53 @@
+//! Managers running against the simulator, shared by the tests.
+use super::*;
+use manager::{UnimotionManager, UnimotionManagerBuilder, Command};
+use event::Event;
+use sim::{Simulator, SimulatorHandle};
+use transport::pipe;
+
+use std::time::{Duration, Instant};
+
+/// Bound on anything a test waits for, only reached when it fails.
+pub(crate) const TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) fn start() -> (UnimotionManager, SimulatorHandle) {
+    start_with(Simulator::new(), UnimotionManagerBuilder::new())
+}
+
+pub(crate) fn start_with(sim: Simulator, builder: UnimotionManagerBuilder) -> (UnimotionManager, SimulatorHandle) {
+    let (local, remote) = pipe(Duration::from_millis(20));
+    let sim = sim.spawn(Box::new(remote)).unwrap();
+    let manager = builder.build_with_transport(local).unwrap();
+    (manager, sim)
+}
+
+/// Returns once the station answered everything sent before, with the datagrams streamed
+/// until then dropped.
+pub(crate) fn sync(manager: &mut UnimotionManager) {
+    manager.send_and_wait(Command::ListSensor, TIMEOUT).unwrap();
+    while manager.data_receiver().try_recv().is_ok() {}
+}
+
+/// Sensors that sent any of the next `n` datagrams.
+pub(crate) fn streaming_sensors(manager: &UnimotionManager, n: usize) -> Vec<u8> {
+    let mut ids = Vec::new();
+    for _ in 0..n {
+        let data = manager.data_receiver().recv_timeout(TIMEOUT).unwrap();
+        if !ids.contains(&data.id) { ids.push(data.id) }
+    }
+    ids.sort();
+    ids
+}
+
+pub(crate) fn next_events(manager: &UnimotionManager, n: usize) -> Vec<Event> {
+    (0..n).filter_map(|_| manager.events().recv_timeout(TIMEOUT).ok()).collect()
+}
+
+/// First fault `update` returns, skipping datagrams.
+pub(crate) fn next_fault(manager: &mut UnimotionManager) -> UnimotionError {
+    let start = Instant::now();
+    loop {
+        assert!(start.elapsed() < TIMEOUT);
+        if let Err(e) = manager.update() { break e }
+    }
+}
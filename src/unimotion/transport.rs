@@ -0,0 +1,202 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use serialport::SerialPort;
+
+use crate::result::UnimotionResult;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe() -> io::Result<()> {
+        let (local, remote) = pipe(Duration::from_millis(10));
+        let (mut local_rx, mut local_tx) = Box::new(local).split()?;
+        let (mut remote_rx, mut remote_tx) = Box::new(remote).split()?;
+
+        local_tx.write_all(b"_alive\n")?;
+        let mut buffer = [0; 4];
+        assert_eq!(remote_rx.read(&mut buffer)?, 4);
+        assert_eq!(&buffer, b"_ali");
+        assert_eq!(remote_rx.read(&mut buffer)?, 3);
+        assert_eq!(&buffer[..3], b"ve\n");
+
+        // Nothing left to read
+        assert_eq!(remote_rx.read(&mut buffer).unwrap_err().kind(), io::ErrorKind::TimedOut);
+
+        remote_tx.write_all(b"_ok\r\n")?;
+        let mut buffer = [0; 16];
+        assert_eq!(local_rx.read(&mut buffer)?, 5);
+
+        // Closing the remote end is seen as end of file
+        drop(remote_tx);
+        assert_eq!(local_rx.read(&mut buffer)?, 0);
+        Ok(())
+    }
+}
+
+pub type TransportReader = Box<dyn Read + Send>;
+pub type TransportWriter = Box<dyn Write + Send>;
+
+/// Byte stream to a UniStation.
+///
+/// It is split into a reader, owned by the ingress thread, and a writer used to send commands.
+/// Reads should time out regularly with `ErrorKind::TimedOut` or `ErrorKind::WouldBlock`,
+/// and a closed connection is reported as a read of 0 bytes.
+pub trait Transport: Send {
+    fn split(self: Box<Self>) -> io::Result<(TransportReader, TransportWriter)>;
+}
+
+/// Opens a new `Transport` to the same UniStation, used to reconnect after the connection is lost.
+///
+/// Any `FnMut() -> UnimotionResult<Box<dyn Transport>>` closure is a connector.
+pub trait Connector: Send {
+    fn connect(&mut self) -> UnimotionResult<Box<dyn Transport>>;
+}
+
+impl<F> Connector for F
+where
+    F: FnMut() -> UnimotionResult<Box<dyn Transport>> + Send,
+{
+    fn connect(&mut self) -> UnimotionResult<Box<dyn Transport>> {
+        self()
+    }
+}
+
+/// UniStation connected to a serial port (the USB dongle, or a pty).
+pub struct SerialTransport {
+    port: Box<dyn SerialPort>,
+}
+
+impl SerialTransport {
+    pub fn new(port: Box<dyn SerialPort>) -> Self {
+        SerialTransport { port }
+    }
+
+    pub fn open(path: &str, baud_rate: u32, read_timeout: Duration) -> serialport::Result<Self> {
+        let port = serialport::new(path, baud_rate)
+            .timeout(read_timeout)
+            .open()?;
+        Ok(Self::new(port))
+    }
+}
+
+impl Transport for SerialTransport {
+    fn split(self: Box<Self>) -> io::Result<(TransportReader, TransportWriter)> {
+        let input = self.port.try_clone()?;
+        Ok((Box::new(input), Box::new(self.port)))
+    }
+}
+
+/// UniStation behind a TCP bridge (or a simulator).
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream, read_timeout: Duration) -> io::Result<Self> {
+        stream.set_read_timeout(Some(read_timeout))?;
+        stream.set_nodelay(true)?;
+        Ok(TcpTransport { stream })
+    }
+
+    pub fn connect(addr: impl ToSocketAddrs, read_timeout: Duration) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?, read_timeout)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn split(self: Box<Self>) -> io::Result<(TransportReader, TransportWriter)> {
+        let input = self.stream.try_clone()?;
+        Ok((Box::new(input), Box::new(self.stream)))
+    }
+}
+
+/// One end of an in-memory pipe created by `pipe`.
+#[derive(Debug)]
+pub struct PipeTransport {
+    reader: PipeReader,
+    writer: PipeWriter,
+}
+
+/// Create both ends of an in-memory, bidirectional pipe.
+///
+/// Whatever is written on one end is read on the other, which is useful to run the manager
+/// against a simulator or a test harness in the same process.
+pub fn pipe(read_timeout: Duration) -> (PipeTransport, PipeTransport) {
+    let (a_tx, a_rx) = crossbeam_channel::unbounded();
+    let (b_tx, b_rx) = crossbeam_channel::unbounded();
+    let a = PipeTransport {
+        reader: PipeReader::new(b_rx, read_timeout),
+        writer: PipeWriter { tx: a_tx },
+    };
+    let b = PipeTransport {
+        reader: PipeReader::new(a_rx, read_timeout),
+        writer: PipeWriter { tx: b_tx },
+    };
+    (a, b)
+}
+
+impl Transport for PipeTransport {
+    fn split(self: Box<Self>) -> io::Result<(TransportReader, TransportWriter)> {
+        Ok((Box::new(self.reader), Box::new(self.writer)))
+    }
+}
+
+#[derive(Debug)]
+pub struct PipeReader {
+    rx: crossbeam_channel::Receiver<Vec<u8>>,
+    pending: Vec<u8>,
+    position: usize,
+    timeout: Duration,
+}
+
+impl PipeReader {
+    fn new(rx: crossbeam_channel::Receiver<Vec<u8>>, timeout: Duration) -> Self {
+        PipeReader { rx, pending: Vec::new(), position: 0, timeout }
+    }
+}
+
+impl Read for PipeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.position >= self.pending.len() {
+            match self.rx.recv_timeout(self.timeout) {
+                Ok(chunk) => {
+                    self.pending = chunk;
+                    self.position = 0;
+                },
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) =>
+                    return Err(io::Error::from(io::ErrorKind::TimedOut)),
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.pending.len() - self.position);
+        buf[..n].copy_from_slice(&self.pending[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[derive(Debug)]
+pub struct PipeWriter {
+    tx: crossbeam_channel::Sender<Vec<u8>>,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.tx.send(buf.to_vec()) {
+            Ok(()) => Ok(buf.len()),
+            Err(_) => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}